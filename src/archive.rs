@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+
+// Single-stream layout of a backup:
+// MAGIC, then for every collection a namespace block ('N', u32 db len, db,
+// u32 collection len, collection) followed by its raw BSON documents ('D', doc),
+// and a final 'E' marker.
+pub const MAGIC: &[u8; 8] = b"MBARCHV1";
+
+const NAMESPACE: u8 = b'N';
+const DOCUMENT: u8 = b'D';
+const END: u8 = b'E';
+
+pub fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(MAGIC)
+}
+
+pub fn write_namespace<W: Write>(writer: &mut W, database: &str, collection: &str) -> io::Result<()> {
+    writer.write_all(&[NAMESPACE])?;
+    write_str(writer, database)?;
+    write_str(writer, collection)
+}
+
+pub fn write_document<W: Write>(writer: &mut W, document: &[u8]) -> io::Result<()> {
+    writer.write_all(&[DOCUMENT])?;
+    writer.write_all(document)
+}
+
+pub fn write_end<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(&[END])
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+pub enum Entry {
+    Namespace(String, String),
+    Document(Vec<u8>),
+    End,
+}
+
+/// Incremental reader of the archive stream: push data, then take entries
+/// until `next_entry` returns `None`.
+#[derive(Default)]
+pub struct ArchiveParser {
+    buffer: Vec<u8>,
+    position: usize,
+    header: bool,
+}
+
+impl ArchiveParser {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        self.buffer.extend_from_slice(data);
+    }
+
+    pub fn next_entry(&mut self) -> crate::Result<Option<Entry>> {
+        if !self.header {
+            if self.available() < MAGIC.len() {
+                return Ok(None);
+            }
+            if &self.buffer[self.position..self.position + MAGIC.len()] != MAGIC {
+                return Err(crate::Error::Corrupt("Not a backup archive".to_string()));
+            }
+            self.position += MAGIC.len();
+            self.header = true;
+        }
+
+        let start = self.position;
+        let entry = match self.buffer.get(start) {
+            None => return Ok(None),
+            Some(&NAMESPACE) => {
+                self.position += 1;
+                let database = match self.read_str()? {
+                    Some(res) => res,
+                    None => return self.rewind(start),
+                };
+                let collection = match self.read_str()? {
+                    Some(res) => res,
+                    None => return self.rewind(start),
+                };
+                Entry::Namespace(database, collection)
+            }
+            Some(&DOCUMENT) => {
+                if self.available() < 5 {
+                    return Ok(None);
+                }
+                let length_bytes = &self.buffer[start + 1..start + 5];
+                let length = i32::from_le_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]);
+                if length < 5 {
+                    return Err(crate::Error::Corrupt(format!("Invalid document length {length}")));
+                }
+                if self.available() < 1 + length as usize {
+                    return Ok(None);
+                }
+                self.position += 1 + length as usize;
+                Entry::Document(self.buffer[start + 1..self.position].to_vec())
+            }
+            Some(&END) => {
+                self.position += 1;
+                Entry::End
+            }
+            Some(other) => {
+                return Err(crate::Error::Corrupt(format!("Unknown archive block '{}'", *other as char)));
+            }
+        };
+
+        Ok(Some(entry))
+    }
+
+    fn available(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
+    fn read_str(&mut self) -> crate::Result<Option<String>> {
+        if self.available() < 4 {
+            return Ok(None);
+        }
+        let bytes = &self.buffer[self.position..self.position + 4];
+        let length = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        if self.available() < 4 + length {
+            return Ok(None);
+        }
+        let start = self.position + 4;
+        self.position = start + length;
+        String::from_utf8(self.buffer[start..self.position].to_vec())
+            .map(Some)
+            .map_err(|_| crate::Error::Corrupt("Invalid namespace in archive".to_string()))
+    }
+
+    fn rewind(&mut self, position: usize) -> crate::Result<Option<Entry>> {
+        self.position = position;
+        Ok(None)
+    }
+}
@@ -0,0 +1,378 @@
+//! Reading of the config file. Its syntax is taken from the extension: JSON5 (JSON with
+//! comments and trailing commas) for `.js` / `.json` / `.json5` / `.jsonc`, TOML or YAML.
+
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use crate::{Error, Result};
+
+/// Names of the config file that are looked for in the config directory, in this order.
+pub const CONFIG_FILES: &[&str] = &["config.js", "config.json", "config.json5", "config.jsonc", "config.toml", "config.yaml", "config.yml"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json5,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_ascii_lowercase()).as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json5,
+        }
+    }
+}
+
+/// TOML has no arrays at the top, the connections are `[[connections]]` tables there.
+#[derive(Deserialize)]
+struct TomlConfig<T> {
+    #[serde(default = "Vec::new")]
+    connections: Vec<T>,
+}
+
+/// Parses the list of connections in `text`, the content of `path`. Errors name the line and
+/// column in `text` as `<path>:<line>:<column>: <message>`.
+pub fn parse<T: DeserializeOwned>(path: &Path, text: &str) -> Result<Vec<T>> {
+    let (message, location) = match ConfigFormat::from_path(path) {
+        ConfigFormat::Json5 => match json5::from_str::<Vec<T>>(text) {
+            Ok(res) => return Ok(res),
+            Err(json5::Error::Message { msg, location }) => {
+                // Syntax errors come with a drawing of the line, only its last "= ..." line is the message
+                let msg = match msg.lines().last().and_then(|line| line.trim().strip_prefix("= ")) {
+                    Some(res) => res.to_string(),
+                    None => msg,
+                };
+                (msg, location.map(|location| (location.line, location.column)))
+            }
+        },
+        ConfigFormat::Toml => match toml::from_str::<TomlConfig<T>>(text) {
+            Ok(res) => return Ok(res.connections),
+            Err(err) => (err.message().to_string(), err.span().map(|span| line_col(text, span.start))),
+        },
+        ConfigFormat::Yaml => match serde_yaml::from_str::<Vec<T>>(text) {
+            Ok(res) => return Ok(res),
+            Err(err) => {
+                let location = err.location().map(|location| (location.line(), location.column()));
+                let message = err.to_string();
+                // The message ends with the location that is put in front of it
+                let message = match location {
+                    Some((line, column)) => message.trim_end_matches(&format!(" at line {line} column {column}")).to_string(),
+                    None => message,
+                };
+                (message, location)
+            }
+        },
+    };
+
+    Err(Error::Config(match location {
+        Some((line, column)) => format!("{}:{line}:{column}: {message}", path.display()),
+        None => format!("{}: {message}", path.display()),
+    }))
+}
+
+/// One-based line and column of the byte `offset` of `text`.
+pub fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|line| line.chars().count()).unwrap_or_default() + 1;
+    (line, column)
+}
+
+/// Where the connections of a config and their fields are, to point messages at them. Only the
+/// fields of the connections themselves are found, not the ones of nested objects.
+pub struct Locations {
+    path: PathBuf,
+    connections: Vec<ConnectionLocation>,
+}
+
+struct ConnectionLocation {
+    start: (usize, usize),
+    keys: HashMap<String, (usize, usize)>,
+}
+
+impl Locations {
+    pub fn new(path: &Path, text: &str) -> Self {
+        let offsets = match ConfigFormat::from_path(path) {
+            ConfigFormat::Json5 => scan_json5(text),
+            ConfigFormat::Toml => scan_toml(text),
+            ConfigFormat::Yaml => scan_yaml(text),
+        };
+
+        let connections = offsets.into_iter()
+            .map(|(start, keys)| ConnectionLocation {
+                start: line_col(text, start),
+                keys: keys.into_iter().map(|(key, offset)| (key, line_col(text, offset))).collect(),
+            })
+            .collect();
+
+        Locations { path: path.to_path_buf(), connections }
+    }
+
+    /// `<path>:<line>:<column>` of the field `key` of the connection `index`, of the connection
+    /// itself when the field is not found.
+    pub fn of(&self, index: usize, key: Option<&str>) -> String {
+        let connection = match self.connections.get(index) {
+            Some(res) => res,
+            None => return self.path.display().to_string(),
+        };
+
+        let (line, column) = key.and_then(|key| connection.keys.get(key)).copied().unwrap_or(connection.start);
+        format!("{}:{line}:{column}", self.path.display())
+    }
+}
+
+/// Offsets of the connections and their keys, an object in the top-level array is a connection.
+fn scan_json5(text: &str) -> Vec<(usize, HashMap<String, usize>)> {
+    let mut connections: Vec<(usize, HashMap<String, usize>)> = Vec::new();
+    let mut depth = 0_usize;
+    // Last string or identifier, a key when a ":" follows it
+    let mut token: Option<(usize, String)> = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '/' if chars.peek().map(|(_, next)| *next) == Some('/') => {
+                chars.by_ref().find(|(_, c)| *c == '\n');
+            }
+            '/' if chars.peek().map(|(_, next)| *next) == Some('*') => {
+                chars.next();
+                let mut last = ' ';
+                chars.by_ref().find(|(_, c)| {
+                    let end = last == '*' && *c == '/';
+                    last = *c;
+                    end
+                });
+            }
+            '"' | '\'' => {
+                let mut value = String::new();
+                while let Some((_, next)) = chars.next() {
+                    match next {
+                        '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+                        next if next == c => break,
+                        next => value.push(next),
+                    }
+                }
+                token = Some((offset, value));
+            }
+            '{' | '[' => {
+                depth += 1;
+                if c == '{' && depth == 2 {
+                    connections.push((offset, HashMap::new()));
+                }
+                token = None;
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                token = None;
+            }
+            ':' => {
+                if let (2, Some((key_offset, key)), Some((_, keys))) = (depth, token.take(), connections.last_mut()) {
+                    keys.entry(key).or_insert(key_offset);
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '$' => {
+                let mut value = c.to_string();
+                while let Some((_, next)) = chars.next_if(|(_, next)| next.is_alphanumeric() || *next == '_' || *next == '$') {
+                    value.push(next);
+                }
+                token = Some((offset, value));
+            }
+            c if c.is_whitespace() => {}
+            _ => token = None,
+        }
+    }
+
+    connections
+}
+
+/// Offsets of the `[[connections]]` tables and their keys.
+fn scan_toml(text: &str) -> Vec<(usize, HashMap<String, usize>)> {
+    let mut connections: Vec<(usize, HashMap<String, usize>)> = Vec::new();
+    let mut in_connection = false;
+
+    for (offset, line) in lines(text) {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if let Some(header) = trimmed.strip_prefix('[') {
+            in_connection = header.strip_prefix('[')
+                .and_then(|header| header.split_once("]]"))
+                .is_some_and(|(name, _)| name.trim() == "connections");
+
+            if in_connection {
+                connections.push((offset + indent, HashMap::new()));
+            }
+            continue;
+        }
+
+        if let (true, Some((key, _)), Some((_, keys))) = (in_connection, trimmed.split_once('='), connections.last_mut()) {
+            let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+            if !key.is_empty() && !key.starts_with('#') {
+                keys.entry(key.to_string()).or_insert(offset + indent);
+            }
+        }
+    }
+
+    connections
+}
+
+/// Offsets of the items of the top-level sequence and their keys.
+fn scan_yaml(text: &str) -> Vec<(usize, HashMap<String, usize>)> {
+    let mut connections: Vec<(usize, HashMap<String, usize>)> = Vec::new();
+    let mut dash_indent = None;
+    let mut key_indent = None;
+
+    for (offset, line) in lines(text) {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut rest = (indent, trimmed);
+
+        if (trimmed == "-" || trimmed.starts_with("- ")) && dash_indent.is_none_or(|dash_indent| dash_indent == indent) {
+            dash_indent = Some(indent);
+            connections.push((offset + indent, HashMap::new()));
+
+            let item = trimmed[1..].trim_start();
+            let item_indent = line.len() - item.len();
+            // A bare "-" has its keys on the following lines
+            key_indent = Some(item_indent).filter(|_| !item.is_empty());
+            rest = (item_indent, item);
+        } else if key_indent.is_none() && dash_indent.is_some_and(|dash_indent| indent > dash_indent) {
+            key_indent = Some(indent);
+        }
+
+        let (indent, item) = rest;
+
+        if key_indent != Some(indent) {
+            continue;
+        }
+
+        if let (Some((key, _)), Some((_, keys))) = (item.split_once(':'), connections.last_mut()) {
+            let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+            keys.entry(key.to_string()).or_insert(offset + indent);
+        }
+    }
+
+    connections
+}
+
+/// Lines of `text` with the offset of their start.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, line.trim_end_matches('\r')))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Connection {
+        name: String,
+        interval: f64,
+    }
+
+    fn error(path: &str, text: &str) -> String {
+        match parse::<Connection>(Path::new(path), text) {
+            Err(Error::Config(message)) => message,
+            res => panic!("expected a config error, got {res:?}"),
+        }
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(ConfigFormat::from_path(Path::new("config.js")), ConfigFormat::Json5);
+        assert_eq!(ConfigFormat::from_path(Path::new("config.jsonc")), ConfigFormat::Json5);
+        assert_eq!(ConfigFormat::from_path(Path::new("config.TOML")), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path(Path::new("config.yml")), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path(Path::new("config")), ConfigFormat::Json5);
+    }
+
+    #[test]
+    fn parse_every_format() {
+        let expected = vec![Connection { name: "a".to_string(), interval: 1.5 }];
+
+        let json5 = "[\n  // comment\n  { name: 'a', interval: 1.5, },\n]";
+        assert_eq!(parse::<Connection>(Path::new("config.js"), json5).unwrap(), expected);
+
+        let toml = "[[connections]]\nname = \"a\"\ninterval = 1.5\n";
+        assert_eq!(parse::<Connection>(Path::new("config.toml"), toml).unwrap(), expected);
+
+        let yaml = "- name: a\n  interval: 1.5\n";
+        assert_eq!(parse::<Connection>(Path::new("config.yaml"), yaml).unwrap(), expected);
+    }
+
+    #[test]
+    fn located_json5_error() {
+        let message = error("config.js", "[\n  { name: 'a', interval: 1 },\n  { name: 'b' interval: 1 },\n]");
+        assert!(message.starts_with("config.js:3:"), "{message}");
+        assert!(!message.contains('\n'), "{message}");
+    }
+
+    #[test]
+    fn located_toml_error() {
+        let message = error("config.toml", "[[connections]]\nname = \"a\"\ninterval = \"often\"\n");
+        assert!(message.starts_with("config.toml:3:"), "{message}");
+    }
+
+    #[test]
+    fn located_yaml_error() {
+        let message = error("config.yaml", "- name: a\n  interval: 1\n- name: b\n  interval: often\n");
+        assert!(message.starts_with("config.yaml:4:"), "{message}");
+        assert!(!message.ends_with("column 13"), "{message}");
+    }
+
+    #[test]
+    fn line_and_column() {
+        let text = "ab\ncdé\nf";
+        assert_eq!(line_col(text, 0), (1, 1));
+        assert_eq!(line_col(text, 3), (2, 1));
+        assert_eq!(line_col(text, 8), (3, 1));
+        assert_eq!(line_col(text, 100), (3, 2));
+    }
+
+    #[test]
+    fn json5_locations() {
+        let text = "[\n  {\n    // name: 'no'\n    name: 'a',\n    \"interval\": 1,\n    nested: { name: 'x' },\n  },\n  { name: 'b' },\n]";
+        let locations = Locations::new(Path::new("config.js"), text);
+
+        assert_eq!(locations.of(0, None), "config.js:2:3");
+        assert_eq!(locations.of(0, Some("name")), "config.js:4:5");
+        assert_eq!(locations.of(0, Some("interval")), "config.js:5:5");
+        assert_eq!(locations.of(0, Some("missing")), "config.js:2:3");
+        assert_eq!(locations.of(1, Some("name")), "config.js:8:5");
+        assert_eq!(locations.of(2, Some("name")), "config.js");
+    }
+
+    #[test]
+    fn toml_locations() {
+        let text = "[[connections]]\nname = \"a\"\n  interval = 1\n\n[other]\nname = \"x\"\n\n[[connections]]\n\"name\" = \"b\"\n";
+        let locations = Locations::new(Path::new("config.toml"), text);
+
+        assert_eq!(locations.of(0, Some("name")), "config.toml:2:1");
+        assert_eq!(locations.of(0, Some("interval")), "config.toml:3:3");
+        assert_eq!(locations.of(1, None), "config.toml:8:1");
+        assert_eq!(locations.of(1, Some("name")), "config.toml:9:1");
+    }
+
+    #[test]
+    fn yaml_locations() {
+        let text = "# connections\n- name: a\n  interval: 1\n  lifecycle:\n    name: x\n-\n  name: b\n";
+        let locations = Locations::new(Path::new("config.yaml"), text);
+
+        assert_eq!(locations.of(0, None), "config.yaml:2:1");
+        assert_eq!(locations.of(0, Some("name")), "config.yaml:2:3");
+        assert_eq!(locations.of(0, Some("interval")), "config.yaml:3:3");
+        assert_eq!(locations.of(1, Some("name")), "config.yaml:7:3");
+    }
+}
@@ -0,0 +1,207 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use mongo_backuper::backuper::{self, Status};
+use mongo_backuper::paths;
+
+/// Create backups of MongoDB
+#[derive(Parser)]
+#[command(name = "mongo_backuper", version, about, arg_required_else_help = true)]
+pub struct Cli {
+    /// Config file [env: MONGO_BACKUPER_CONFIG] [default: <dir>/config.js]
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Directory of the config and the backups [env: MONGO_BACKUPER_DIR] [default: /MongoBackups when it
+    /// exists (C:\MongoBackups on Windows), the XDG config and data directories otherwise]
+    #[arg(long, global = true, value_name = "DIR")]
+    dir: Option<PathBuf>,
+
+    /// Directory of the runs of every connection without storage of its own [env: MONGO_BACKUPER_OUTPUT] [default: <dir>/Backups]
+    #[arg(long, global = true, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Back up right away and keep following the schedule, or exit after it with --once
+    Backup {
+        /// Only this connection
+        #[arg(long, short)]
+        connection: Option<String>,
+        /// Exit after one backup of every connection
+        #[arg(long)]
+        once: bool,
+    },
+    /// Back up every connection on its schedule until the process is stopped
+    Daemon,
+    /// List the runs of the connections in every destination
+    List {
+        /// Only this connection
+        #[arg(long, short)]
+        connection: Option<String>,
+    },
+    /// Load a run back into MongoDB
+    Restore {
+        #[arg(long, short)]
+        connection: String,
+        /// Destination or tier to restore from [default: the first destination]
+        #[arg(long)]
+        destination: Option<String>,
+        /// Run to restore [default: the newest run]
+        #[arg(long)]
+        run: Option<String>,
+        /// Server to restore into [default: the url of the connection]
+        #[arg(long)]
+        url: Option<String>,
+        /// Drop every collection before it is restored
+        #[arg(long)]
+        drop: bool,
+        /// Key or age identity file of encrypted runs [default: the key of the connection]
+        #[arg(long, value_name = "FILE")]
+        key: Option<String>,
+    },
+    /// Check a run against its manifest and signature
+    Verify {
+        /// Only this connection
+        #[arg(long, short)]
+        connection: Option<String>,
+        /// Destination or tier to check [default: the first destination]
+        #[arg(long)]
+        destination: Option<String>,
+        /// Run to check [default: the newest run]
+        #[arg(long)]
+        run: Option<String>,
+        /// Key or age identity file to count the documents of encrypted runs
+        #[arg(long, value_name = "FILE")]
+        key: Option<String>,
+    },
+    /// Delete or move old runs as the retention says, without backing up
+    Prune {
+        /// Only this connection
+        #[arg(long, short)]
+        connection: Option<String>,
+    },
+    /// Work with the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Re-encrypt the backups of a connection with its current key
+    Rekey {
+        connection: String,
+        /// File with the previous key or age identity
+        old_key: String,
+    },
+    /// Write a backup of a connection as an archive stream to stdout
+    Stream {
+        connection: String,
+    },
+    /// Restore an archive stream from stdin
+    RestoreStream {
+        url: String,
+        /// Drop every collection before it is restored
+        #[arg(long)]
+        drop: bool,
+    },
+    /// Read commands from the terminal until "quit"
+    Repl,
+    /// Install a service for automatic backups
+    #[cfg(target_os = "windows")]
+    Install,
+    /// Remove the service for automatic backups
+    #[cfg(target_os = "windows")]
+    Uninstall,
+    /// Restart the service for automatic backups
+    #[cfg(target_os = "windows")]
+    Restart,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Check the config without connecting to anything
+    Check,
+}
+
+/// Parses the arguments of the process and runs the command.
+pub fn main() -> Status {
+    run(Cli::parse())
+}
+
+fn run(cli: Cli) -> Status {
+    if let Some(dir) = cli.dir {
+        if !paths::set_directory(dir) {
+            mongo_backuper::logger::warn("--dir can not be changed any more");
+        }
+    }
+
+    if let Some(config) = cli.config {
+        if !paths::set_config_path(config) {
+            mongo_backuper::logger::warn("--config can not be changed any more");
+        }
+    }
+
+    if let Some(output) = cli.output {
+        if !paths::set_output(output) {
+            mongo_backuper::logger::warn("--output can not be changed any more");
+        }
+    }
+
+    match cli.command {
+        Command::Backup { connection, once } => backuper::backup(connection.as_deref(), once),
+        Command::Daemon => backuper::daemon(),
+        Command::List { connection } => backuper::list(connection.as_deref()),
+        Command::Restore { connection, destination, run, url, drop, key } => {
+            backuper::restore(&connection, destination.as_deref(), run.as_deref(), url.as_deref(), drop, key.as_deref())
+        }
+        Command::Verify { connection, destination, run, key } => {
+            backuper::verify(connection.as_deref(), destination.as_deref(), run.as_deref(), key.as_deref())
+        }
+        Command::Prune { connection } => backuper::prune(connection.as_deref()),
+        Command::Config { command: ConfigCommand::Check } => backuper::check_config(),
+        Command::Rekey { connection, old_key } => backuper::rekey(&connection, &old_key),
+        Command::Stream { connection } => backuper::stream(&connection),
+        Command::RestoreStream { url, drop } => backuper::restore_stream(&url, drop),
+        Command::Repl => repl(),
+        #[cfg(target_os = "windows")]
+        Command::Install => crate::core::install(),
+        #[cfg(target_os = "windows")]
+        Command::Uninstall => crate::core::uninstall(),
+        #[cfg(target_os = "windows")]
+        Command::Restart => crate::core::restart(),
+    }
+}
+
+/// Runs the commands typed into the terminal, the same ones as on the command line.
+fn repl() -> Status {
+    loop {
+        println!("{} Write command... // Write \"help\" to get commands", mongo_backuper::logger::colors::green("{INPUT}"));
+
+        let line = match mongo_backuper::exts::read_line() {
+            Some(res) => res,
+            None => return Status::Ok,
+        };
+
+        if line == "quit" {
+            return Status::Ok;
+        }
+
+        let args = std::iter::once("mongo_backuper").chain(line.split_whitespace());
+
+        match Cli::try_parse_from(args) {
+            Ok(Cli { command: Command::Repl, .. }) => {}
+            Ok(cli) => {
+                let status = run(cli);
+                if status != Status::Ok {
+                    mongo_backuper::logger::warn_string(format!("Command failed with exit code {}", status.code()));
+                }
+            }
+            Err(err) => {
+                err.print().unwrap_or_default();
+            }
+        }
+    }
+}
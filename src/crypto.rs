@@ -0,0 +1,528 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt, io::{Read, Write}};
+
+use crate::report::EncryptionReport;
+use crate::{Error, Result};
+
+// Encrypted object layout:
+// MAGIC, 8 byte key id, 7 byte nonce prefix, then the plaintext in chunks of
+// CHUNK_SIZE sealed with AES-256-GCM. The nonce of a chunk is the prefix, the
+// big endian chunk counter and 1 for the last chunk (0 otherwise), so chunks
+// can not be reordered, dropped or cut off. The header is the associated data of every chunk.
+pub const MAGIC: &[u8; 8] = b"MBCRYPT1";
+pub const ALGORITHM: &str = "aes-256-gcm";
+pub const EXTENSION: &str = ".enc";
+
+const KEY_ID_SIZE: usize = 8;
+const PREFIX_SIZE: usize = 7;
+const HEADER_SIZE: usize = MAGIC.len() + KEY_ID_SIZE + PREFIX_SIZE;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptionConfig {
+    /// File with the 32 byte key, raw or as hex / base64 text.
+    #[serde(rename = "keyFile")]
+    pub key_file: Option<String>,
+    /// Environment variable with the key as hex / base64 text.
+    #[serde(rename = "keyEnv")]
+    pub key_env: Option<String>,
+    /// age public keys (`age1...`), used instead of a key.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+}
+
+impl EncryptionConfig {
+    pub fn load(&self, name: &str) -> Result<Encryption> {
+        if !self.recipients.is_empty() {
+            if self.key_file.is_some() || self.key_env.is_some() {
+                return Err(Error::Config(format!("Encryption of \"{name}\" takes either a key or \"recipients\"")));
+            }
+
+            let recipients = self.recipients.iter()
+                .map(|recipient| recipient.trim().parse::<age::x25519::Recipient>()
+                    .map_err(|err| Error::Config(format!("Invalid recipient \"{recipient}\" of \"{name}\": {err}"))))
+                .collect::<Result<Vec<_>>>()?;
+
+            return Ok(Encryption::Recipients(recipients));
+        }
+
+        let key = match (&self.key_file, &self.key_env) {
+            (Some(path), _) => {
+                let data = std::fs::read(path)
+                    .map_err(|err| Error::Config(format!("Failed to read key file \"{path}\" of \"{name}\": {err}")))?;
+                Key::parse(&data)?
+            }
+            (None, Some(variable)) => match std::env::var(variable) {
+                Ok(value) => {
+                    crate::logger::add_secret(&value);
+                    Key::parse(value.as_bytes())?
+                }
+                Err(_) => return Err(Error::Config(format!("Environment variable \"{variable}\" of \"{name}\" is not set"))),
+            },
+            (None, None) => return Err(Error::Config(format!("Encryption of \"{name}\" needs \"keyFile\", \"keyEnv\" or \"recipients\""))),
+        };
+
+        Ok(Encryption::Key(key))
+    }
+}
+
+/// How the runs of a job are encrypted.
+#[derive(Clone, Debug)]
+pub enum Encryption {
+    /// Every run is encrypted with the same key.
+    Key(Key),
+    /// Every run gets a random key that is stored in the manifest, encrypted to the
+    /// age recipients. Only the holders of their identities can read the run.
+    Recipients(Vec<age::x25519::Recipient>),
+}
+
+impl From<Key> for Encryption {
+    fn from(key: Key) -> Self {
+        Encryption::Key(key)
+    }
+}
+
+impl Encryption {
+    /// Key of a new run and its description for the manifest.
+    pub fn run_key(&self) -> Result<(Key, EncryptionReport)> {
+        let (key, wrapped_key, recipients) = match self {
+            Encryption::Key(key) => (key.clone(), None, Vec::new()),
+            Encryption::Recipients(recipients) => {
+                let key = Key::generate();
+                let wrapped = wrap_key(&key, recipients)?;
+                (key, Some(wrapped), recipients.iter().map(|recipient| recipient.to_string()).collect())
+            }
+        };
+
+        let report = EncryptionReport {
+            algorithm: ALGORITHM.to_string(),
+            key_id: key.id(),
+            recipients,
+            wrapped_key,
+        };
+
+        Ok((key, report))
+    }
+
+    /// Whether a run described by `encryption` is already encrypted this way.
+    pub fn matches(&self, encryption: &EncryptionReport) -> bool {
+        match self {
+            Encryption::Key(key) => encryption.wrapped_key.is_none() && encryption.key_id == key.id(),
+            Encryption::Recipients(recipients) => {
+                encryption.wrapped_key.is_some()
+                    && encryption.recipients.len() == recipients.len()
+                    && recipients.iter().all(|recipient| encryption.recipients.contains(&recipient.to_string()))
+            }
+        }
+    }
+}
+
+/// What reads an encrypted run: the key of `Encryption::Key` or the age identities
+/// matching the recipients of `Encryption::Recipients`.
+pub enum Secret {
+    Key(Key),
+    Identities(Vec<age::x25519::Identity>),
+}
+
+impl From<Key> for Secret {
+    fn from(key: Key) -> Self {
+        Secret::Key(key)
+    }
+}
+
+impl Secret {
+    /// Reads a key file or, when it holds `AGE-SECRET-KEY-1...` lines, an identity file.
+    pub fn from_file(path: &str) -> Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|err| Error::Config(format!("Failed to read \"{path}\": {err}")))?;
+
+        match String::from_utf8_lossy(&data).contains("AGE-SECRET-KEY-") {
+            true => Secret::parse_identities(&String::from_utf8_lossy(&data)),
+            false => Ok(Secret::Key(Key::parse(&data)?)),
+        }
+    }
+
+    /// Reads identities (`AGE-SECRET-KEY-1...`) as written by `age-keygen`.
+    pub fn identity_file(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Error::Config(format!("Failed to read identity file \"{path}\": {err}")))?;
+        Secret::parse_identities(&text)
+    }
+
+    pub fn parse_identities(text: &str) -> Result<Self> {
+        let identities = text.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.parse::<age::x25519::Identity>()
+                .map_err(|err| Error::Config(format!("Invalid age identity: {err}"))))
+            .collect::<Result<Vec<_>>>()?;
+
+        match identities.is_empty() {
+            true => Err(Error::Config("No age identity found".to_string())),
+            false => Ok(Secret::Identities(identities)),
+        }
+    }
+
+    /// Key of a run described by `encryption`.
+    pub fn unlock(&self, encryption: &EncryptionReport) -> Result<Key> {
+        let key = match (self, &encryption.wrapped_key) {
+            (Secret::Key(key), None) => key.clone(),
+            (Secret::Identities(identities), Some(wrapped)) => unwrap_key(wrapped, identities)?,
+            (Secret::Key(_), Some(_)) => {
+                return Err(Error::Config("Run is encrypted to age recipients, an identity is needed to read it".to_string()));
+            }
+            (Secret::Identities(_), None) => {
+                return Err(Error::Config("Run is encrypted with a key, an identity can not read it".to_string()));
+            }
+        };
+
+        match key.id() == encryption.key_id {
+            true => Ok(key),
+            false => Err(Error::Config(format!("Run is encrypted with key {}, the given key is {}", encryption.key_id, key.id()))),
+        }
+    }
+}
+
+fn wrap_key(key: &Key, recipients: &[age::x25519::Recipient]) -> Result<String> {
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|recipient| recipient as &dyn age::Recipient))
+        .map_err(|err| Error::Config(format!("Failed to encrypt to the recipients: {err}")))?;
+
+    let mut wrapped = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut wrapped)?;
+    writer.write_all(&key.bytes)?;
+    writer.finish()?;
+
+    Ok(STANDARD.encode(wrapped))
+}
+
+fn unwrap_key(wrapped: &str, identities: &[age::x25519::Identity]) -> Result<Key> {
+    let wrapped = STANDARD.decode(wrapped)
+        .map_err(|err| Error::Corrupt(format!("Invalid wrapped key: {err}")))?;
+
+    let decryptor = age::Decryptor::new_buffered(wrapped.as_slice())
+        .map_err(|err| Error::Corrupt(format!("Invalid wrapped key: {err}")))?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity as &dyn age::Identity))
+        .map_err(|err| Error::Config(format!("None of the identities can read the run: {err}")))?;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    Key::parse(&bytes)
+}
+
+/// AES-256 key of encrypted backups.
+#[derive(Clone)]
+pub struct Key {
+    bytes: [u8; 32],
+    id: [u8; KEY_ID_SIZE],
+}
+
+impl Key {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        let mut id = [0; KEY_ID_SIZE];
+        id.copy_from_slice(&Sha256::digest(bytes)[..KEY_ID_SIZE]);
+        Key { bytes, id }
+    }
+
+    pub fn generate() -> Self {
+        Key::from_bytes(Aes256Gcm::generate_key(&mut OsRng).into())
+    }
+
+    /// Accepts 32 raw bytes or their hex / base64 encoding.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        Ok(Key::from_bytes(decode_key(data, "Encryption key")?))
+    }
+
+    /// Hex id written into the manifest and the encrypted objects to recognize the key.
+    pub fn id(&self) -> String {
+        hex::encode(self.id)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.bytes.into())
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key({})", self.id())
+    }
+}
+
+/// 32 raw bytes or their hex / base64 encoding.
+pub(crate) fn decode_key(data: &[u8], what: &str) -> Result<[u8; 32]> {
+    let bytes = match data.len() {
+        32 => data.to_vec(),
+        _ => {
+            let text = String::from_utf8_lossy(data);
+            let text = text.trim();
+            hex::decode(text)
+                .or_else(|_| STANDARD.decode(text))
+                .map_err(|_| Error::Config(format!("{what} is neither hex nor base64")))?
+        }
+    };
+
+    <[u8; 32]>::try_from(bytes.as_slice())
+        .map_err(|_| Error::Config(format!("{what} has {} bytes, expected 32", bytes.len())))
+}
+
+fn nonce(prefix: &[u8], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[PREFIX_SIZE..PREFIX_SIZE + 4].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Streaming encryption: push plaintext with `update`, close the stream with `finish`.
+pub struct Encryptor {
+    cipher: Aes256Gcm,
+    header: Vec<u8>,
+    counter: u32,
+    buffer: Vec<u8>,
+    started: bool,
+}
+
+impl Encryptor {
+    pub fn new(key: &Key) -> Self {
+        let random = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&key.id);
+        header.extend_from_slice(&random[..PREFIX_SIZE]);
+
+        Encryptor { cipher: key.cipher(), header, counter: 0, buffer: Vec::new(), started: false }
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = self.take_header();
+        self.buffer.extend_from_slice(data);
+
+        // The last chunk is sealed by `finish`, so one chunk always stays buffered
+        while self.buffer.len() > CHUNK_SIZE {
+            let rest = self.buffer.split_off(CHUNK_SIZE);
+            let chunk = std::mem::replace(&mut self.buffer, rest);
+            out.extend(self.seal(&chunk, false)?);
+        }
+
+        Ok(out)
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        let mut out = self.take_header();
+        let chunk = std::mem::take(&mut self.buffer);
+        out.extend(self.seal(&chunk, true)?);
+        Ok(out)
+    }
+
+    fn take_header(&mut self) -> Vec<u8> {
+        match self.started {
+            true => Vec::new(),
+            false => {
+                self.started = true;
+                self.header.clone()
+            }
+        }
+    }
+
+    fn seal(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+        let nonce = nonce(&self.header[MAGIC.len() + KEY_ID_SIZE..], self.counter, last);
+        self.counter = self.counter.checked_add(1)
+            .ok_or_else(|| Error::Storage("Encrypted object is too large".to_string()))?;
+
+        self.cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: chunk, aad: &self.header })
+            .map_err(|_| Error::Storage("Encryption failed".to_string()))
+    }
+}
+
+/// Streaming decryption: push ciphertext with `update`, `finish` checks that the stream was complete.
+pub struct Decryptor {
+    cipher: Aes256Gcm,
+    key_id: String,
+    header: Option<Vec<u8>>,
+    counter: u32,
+    buffer: Vec<u8>,
+}
+
+impl Decryptor {
+    pub fn new(key: &Key) -> Self {
+        Decryptor { cipher: key.cipher(), key_id: key.id(), header: None, counter: 0, buffer: Vec::new() }
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.buffer.extend_from_slice(data);
+        let mut out = Vec::new();
+
+        if self.header.is_none() {
+            if self.buffer.len() < HEADER_SIZE {
+                return Ok(out);
+            }
+
+            let rest = self.buffer.split_off(HEADER_SIZE);
+            let header = std::mem::replace(&mut self.buffer, rest);
+            self.header = Some(check_header(&header, &self.key_id)?);
+        }
+
+        while self.buffer.len() > CHUNK_SIZE + TAG_SIZE {
+            let rest = self.buffer.split_off(CHUNK_SIZE + TAG_SIZE);
+            let chunk = std::mem::replace(&mut self.buffer, rest);
+            out.extend(self.open(&chunk, false)?);
+        }
+
+        Ok(out)
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        if self.header.is_none() {
+            return Err(Error::Corrupt("Encrypted object is truncated".to_string()));
+        }
+
+        let chunk = std::mem::take(&mut self.buffer);
+        self.open(&chunk, true)
+    }
+
+    fn open(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+        let header = self.header.as_deref().unwrap_or_default();
+        let nonce = nonce(&header[MAGIC.len() + KEY_ID_SIZE..], self.counter, last);
+        self.counter = self.counter.wrapping_add(1);
+
+        self.cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: chunk, aad: header })
+            .map_err(|_| Error::Corrupt("Encrypted object is damaged or truncated".to_string()))
+    }
+}
+
+fn check_header(header: &[u8], key_id: &str) -> Result<Vec<u8>> {
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(Error::Corrupt("Object is not encrypted".to_string()));
+    }
+
+    let object_key = hex::encode(&header[MAGIC.len()..MAGIC.len() + KEY_ID_SIZE]);
+    if object_key != key_id {
+        return Err(Error::Config(format!("Object is encrypted with key {object_key}, the given key is {key_id}")));
+    }
+
+    Ok(header.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypt(key: &Key, data: &[u8], piece: usize) -> Vec<u8> {
+        let mut encryptor = Encryptor::new(key);
+        let mut out = Vec::new();
+        for chunk in data.chunks(piece) {
+            out.extend(encryptor.update(chunk).unwrap());
+        }
+        out.extend(encryptor.finish().unwrap());
+        out
+    }
+
+    fn decrypt(key: &Key, data: &[u8], piece: usize) -> Result<Vec<u8>> {
+        let mut decryptor = Decryptor::new(key);
+        let mut out = Vec::new();
+        for chunk in data.chunks(piece) {
+            out.extend(decryptor.update(chunk)?);
+        }
+        out.extend(decryptor.finish()?);
+        Ok(out)
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = Key::generate();
+
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE + 17] {
+            let data = sample(len);
+            let encrypted = encrypt(&key, &data, 1000);
+            assert_eq!(encrypted.len(), HEADER_SIZE + len + len.div_ceil(CHUNK_SIZE).max(1) * TAG_SIZE);
+            assert_eq!(decrypt(&key, &encrypted, 777).unwrap(), data, "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn tampered_chunk() {
+        let key = Key::generate();
+        let mut encrypted = encrypt(&key, &sample(2 * CHUNK_SIZE), 4096);
+        encrypted[HEADER_SIZE + CHUNK_SIZE / 2] ^= 1;
+
+        assert!(matches!(decrypt(&key, &encrypted, 4096), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn tampered_header() {
+        let key = Key::generate();
+        let mut encrypted = encrypt(&key, &sample(100), 4096);
+        encrypted[HEADER_SIZE - 1] ^= 1;
+
+        assert!(matches!(decrypt(&key, &encrypted, 4096), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn truncated_at_chunk_boundary() {
+        let key = Key::generate();
+        let encrypted = encrypt(&key, &sample(3 * CHUNK_SIZE), 4096);
+
+        // Whole chunks are dropped, the chunk before them is not marked as the last one
+        let truncated = &encrypted[..HEADER_SIZE + 2 * (CHUNK_SIZE + TAG_SIZE)];
+        assert!(matches!(decrypt(&key, truncated, 4096), Err(Error::Corrupt(_))));
+        assert!(matches!(decrypt(&key, &encrypted[..HEADER_SIZE - 1], 4096), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn reordered_chunks() {
+        let key = Key::generate();
+        let encrypted = encrypt(&key, &sample(3 * CHUNK_SIZE), 4096);
+        let size = CHUNK_SIZE + TAG_SIZE;
+
+        let mut reordered = encrypted[..HEADER_SIZE].to_vec();
+        reordered.extend_from_slice(&encrypted[HEADER_SIZE + size..HEADER_SIZE + 2 * size]);
+        reordered.extend_from_slice(&encrypted[HEADER_SIZE..HEADER_SIZE + size]);
+        reordered.extend_from_slice(&encrypted[HEADER_SIZE + 2 * size..]);
+
+        assert!(matches!(decrypt(&key, &reordered, 4096), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn other_key() {
+        let encrypted = encrypt(&Key::generate(), &sample(100), 4096);
+
+        assert!(matches!(decrypt(&Key::generate(), &encrypted, 4096), Err(Error::Config(_))));
+        assert!(matches!(decrypt(&Key::generate(), &sample(100), 4096), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn parse_key() {
+        let bytes = [7; 32];
+        let key = Key::from_bytes(bytes);
+
+        assert_eq!(Key::parse(&bytes).unwrap().id(), key.id());
+        assert_eq!(Key::parse(hex::encode(bytes).as_bytes()).unwrap().id(), key.id());
+        assert_eq!(Key::parse(format!("{}\n", STANDARD.encode(bytes)).as_bytes()).unwrap().id(), key.id());
+        assert!(Key::parse(b"too short").is_err());
+    }
+
+    #[test]
+    fn recipients() {
+        let identity = age::x25519::Identity::generate();
+        let encryption = Encryption::Recipients(vec![identity.to_public()]);
+        let (key, report) = encryption.run_key().unwrap();
+
+        assert!(encryption.matches(&report));
+        let secret = Secret::Identities(vec![identity]);
+        assert_eq!(secret.unlock(&report).unwrap().id(), key.id());
+
+        let other = Secret::Identities(vec![age::x25519::Identity::generate()]);
+        assert!(other.unlock(&report).is_err());
+        assert!(Secret::Key(key).unlock(&report).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Mongo(mongodb::error::Error),
+    Json(serde_json::Error),
+    Http(reqwest::Error),
+    Ssh(ssh2::Error),
+    Parquet(parquet::errors::ParquetError),
+    Sqlite(rusqlite::Error),
+    Config(String),
+    Storage(String),
+    Corrupt(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {err}"),
+            Error::Mongo(err) => write!(f, "MongoDB error: {err}"),
+            Error::Json(err) => write!(f, "JSON error: {err}"),
+            Error::Http(err) => write!(f, "HTTP error: {err}"),
+            Error::Ssh(err) => write!(f, "SSH error: {err}"),
+            Error::Parquet(err) => write!(f, "Parquet error: {err}"),
+            Error::Sqlite(err) => write!(f, "SQLite error: {err}"),
+            Error::Config(msg) => write!(f, "Config error: {msg}"),
+            Error::Storage(msg) => write!(f, "Storage error: {msg}"),
+            Error::Corrupt(msg) => write!(f, "Corrupted backup: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Mongo(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::Http(err) => Some(err),
+            Error::Ssh(err) => Some(err),
+            Error::Parquet(err) => Some(err),
+            Error::Sqlite(err) => Some(err),
+            Error::Config(_) | Error::Storage(_) | Error::Corrupt(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<mongodb::error::Error> for Error {
+    fn from(err: mongodb::error::Error) -> Self {
+        Error::Mongo(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<ssh2::Error> for Error {
+    fn from(err: ssh2::Error) -> Self {
+        Error::Ssh(err)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Sqlite(err)
+    }
+}
@@ -0,0 +1,9 @@
+//! Copies of the collections for other tools, written next to (or instead of) the dump.
+
+mod parquet;
+mod sqlite;
+
+pub use self::parquet::{Column, ColumnType, Nested, ParquetOptions, EXTRA_COLUMN};
+pub use self::sqlite::{SqliteOptions, SNAPSHOT_FILE};
+pub(crate) use self::parquet::{read_rows, ParquetWriter};
+pub(crate) use self::sqlite::{count_rows, SqliteExport};
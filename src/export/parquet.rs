@@ -0,0 +1,421 @@
+use bson::{Bson, Document, RawDocumentBuf};
+use parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::format::MilliSeconds;
+use parquet::schema::types::Type;
+use serde::{Deserialize, Serialize};
+use std::{io::Write, sync::{Arc, Mutex}};
+
+use crate::output::OutputFile;
+use crate::report::FileReport;
+use crate::{Error, Result};
+
+/// Column with the fields of a document that do not fit the inferred schema, as a JSON object.
+pub const EXTRA_COLUMN: &str = "_extra";
+
+const ROW_GROUP_SIZE: usize = 50_000;
+
+/// How the export is configured, `parquet` section of a connection.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ParquetOptions {
+    /// Documents read to infer the schema of a collection.
+    #[serde(default = "default_sample_size", rename = "sampleSize")]
+    pub sample_size: usize,
+    #[serde(default)]
+    pub nested: Nested,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions { sample_size: default_sample_size(), nested: Nested::default() }
+    }
+}
+
+fn default_sample_size() -> usize {
+    1000
+}
+
+/// What happens with embedded documents: `flatten` turns them into `parent.child`
+/// columns, `json` stores them as a JSON string. Arrays are always JSON.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Nested {
+    #[default]
+    Flatten,
+    Json,
+}
+
+/// One column of the inferred schema, recorded in the manifest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ColumnType,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Boolean,
+    Int32,
+    Int64,
+    Double,
+    /// Strings, object ids and decimals.
+    String,
+    /// Dates as milliseconds since the epoch (UTC).
+    Timestamp,
+    /// Relaxed Extended JSON of anything else, also of fields whose type differs between documents.
+    Json,
+}
+
+impl ColumnType {
+    fn of(value: &Bson) -> Option<ColumnType> {
+        match value {
+            Bson::Null | Bson::Undefined => None,
+            Bson::Boolean(_) => Some(ColumnType::Boolean),
+            Bson::Int32(_) => Some(ColumnType::Int32),
+            Bson::Int64(_) => Some(ColumnType::Int64),
+            Bson::Double(_) => Some(ColumnType::Double),
+            Bson::String(_) | Bson::ObjectId(_) | Bson::Decimal128(_) | Bson::Symbol(_) => Some(ColumnType::String),
+            Bson::DateTime(_) => Some(ColumnType::Timestamp),
+            _ => Some(ColumnType::Json),
+        }
+    }
+
+    /// Type that holds the values of both types.
+    fn merge(self, other: ColumnType) -> ColumnType {
+        use ColumnType::*;
+
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Int32, Int64) | (Int64, Int32) => Int64,
+            (Int32 | Int64, Double) | (Double, Int32 | Int64) => Double,
+            _ => Json,
+        }
+    }
+
+    fn parquet_type(&self, name: &str) -> Result<Type> {
+        let (physical, logical) = match self {
+            ColumnType::Boolean => (PhysicalType::BOOLEAN, None),
+            ColumnType::Int32 => (PhysicalType::INT32, None),
+            ColumnType::Int64 => (PhysicalType::INT64, None),
+            ColumnType::Double => (PhysicalType::DOUBLE, None),
+            ColumnType::String => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            ColumnType::Timestamp => (PhysicalType::INT64, Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: true,
+                unit: TimeUnit::MILLIS(MilliSeconds::new()),
+            })),
+            ColumnType::Json => (PhysicalType::BYTE_ARRAY, Some(LogicalType::Json)),
+        };
+
+        Ok(Type::primitive_type_builder(name, physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical)
+            .build()?)
+    }
+}
+
+/// Values of one column for the rows of the current row group.
+enum Values {
+    Boolean(Vec<bool>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Bytes(Vec<ByteArray>),
+}
+
+impl Values {
+    fn new(kind: ColumnType) -> Self {
+        match kind {
+            ColumnType::Boolean => Values::Boolean(Vec::new()),
+            ColumnType::Int32 => Values::Int32(Vec::new()),
+            ColumnType::Int64 | ColumnType::Timestamp => Values::Int64(Vec::new()),
+            ColumnType::Double => Values::Double(Vec::new()),
+            ColumnType::String | ColumnType::Json => Values::Bytes(Vec::new()),
+        }
+    }
+
+    /// Adds `value` when it fits the column, gives it back otherwise.
+    fn push(&mut self, kind: ColumnType, value: Bson) -> std::result::Result<(), Bson> {
+        match (self, kind, value) {
+            (Values::Boolean(values), _, Bson::Boolean(value)) => values.push(value),
+            (Values::Int32(values), _, Bson::Int32(value)) => values.push(value),
+            (Values::Int64(values), ColumnType::Int64, Bson::Int32(value)) => values.push(value as i64),
+            (Values::Int64(values), ColumnType::Int64, Bson::Int64(value)) => values.push(value),
+            (Values::Int64(values), ColumnType::Timestamp, Bson::DateTime(value)) => values.push(value.timestamp_millis()),
+            (Values::Double(values), _, Bson::Int32(value)) => values.push(value as f64),
+            (Values::Double(values), _, Bson::Int64(value)) => values.push(value as f64),
+            (Values::Double(values), _, Bson::Double(value)) => values.push(value),
+            (Values::Bytes(values), ColumnType::String, Bson::String(value) | Bson::Symbol(value)) => values.push(value.into_bytes().into()),
+            (Values::Bytes(values), ColumnType::String, Bson::ObjectId(value)) => values.push(value.to_hex().into_bytes().into()),
+            (Values::Bytes(values), ColumnType::String, Bson::Decimal128(value)) => values.push(value.to_string().into_bytes().into()),
+            (Values::Bytes(values), ColumnType::Json, value) => values.push(value.into_relaxed_extjson().to_string().into_bytes().into()),
+            (_, _, value) => return Err(value),
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, writer: &mut ColumnWriter<'_>, levels: &[i16]) -> Result<()> {
+        match (self, writer) {
+            (Values::Boolean(values), ColumnWriter::BoolColumnWriter(writer)) => writer.write_batch(values, Some(levels), None)?,
+            (Values::Int32(values), ColumnWriter::Int32ColumnWriter(writer)) => writer.write_batch(values, Some(levels), None)?,
+            (Values::Int64(values), ColumnWriter::Int64ColumnWriter(writer)) => writer.write_batch(values, Some(levels), None)?,
+            (Values::Double(values), ColumnWriter::DoubleColumnWriter(writer)) => writer.write_batch(values, Some(levels), None)?,
+            (Values::Bytes(values), ColumnWriter::ByteArrayColumnWriter(writer)) => writer.write_batch(values, Some(levels), None)?,
+            _ => return Err(Error::Corrupt("Parquet column does not match its values".to_string())),
+        };
+
+        Ok(())
+    }
+}
+
+/// Bytes written by the Parquet writer that are not yet pushed to the `OutputFile`.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        self.0.lock().map(|mut data| std::mem::take(&mut *data)).unwrap_or_default()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.0.lock() {
+            Ok(mut data) => {
+                data.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            Err(_) => Err(std::io::Error::other("Parquet buffer is poisoned")),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the documents of one collection as a Parquet file. The first `sample_size`
+/// documents are held back to infer the schema, the rest is written in row groups.
+pub(crate) struct ParquetWriter {
+    options: ParquetOptions,
+    output: OutputFile,
+    sample: Vec<Document>,
+    columns: Vec<Column>,
+    values: Vec<(Values, Vec<i16>)>,
+    rows: usize,
+    buffer: SharedBuffer,
+    writer: Option<SerializedFileWriter<SharedBuffer>>,
+}
+
+impl ParquetWriter {
+    pub fn new(output: OutputFile, options: ParquetOptions) -> Self {
+        ParquetWriter {
+            options,
+            output,
+            sample: Vec::new(),
+            columns: Vec::new(),
+            values: Vec::new(),
+            rows: 0,
+            buffer: SharedBuffer::default(),
+            writer: None,
+        }
+    }
+
+    pub async fn write(&mut self, doc: &RawDocumentBuf) -> Result<()> {
+        let doc = doc.to_document().map_err(|err| Error::Corrupt(err.to_string()))?;
+        self.output.documents += 1;
+
+        if self.writer.is_some() {
+            return self.push(doc).await;
+        }
+
+        self.sample.push(doc);
+
+        match self.sample.len() >= self.options.sample_size.max(1) {
+            true => self.start().await,
+            false => Ok(()),
+        }
+    }
+
+    pub async fn finish(mut self, database: Option<String>, collection: Option<String>) -> Result<FileReport> {
+        if self.writer.is_none() {
+            self.start().await?;
+        }
+
+        self.write_row_group().await?;
+
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+
+        self.output.write_all(&self.buffer.take())?;
+
+        let mut report = self.output.finish(database, collection).await?;
+        report.columns = self.columns;
+        Ok(report)
+    }
+
+    /// Infers the schema from the sample and writes the sampled documents.
+    async fn start(&mut self) -> Result<()> {
+        let mut found: Vec<(String, Option<ColumnType>)> = Vec::new();
+
+        for doc in &self.sample {
+            for (name, value) in flatten(doc.clone(), self.options.nested) {
+                let kind = ColumnType::of(&value);
+
+                match found.iter_mut().find(|(other, _)| *other == name) {
+                    Some((_, existing)) => {
+                        *existing = match (*existing, kind) {
+                            (Some(existing), Some(kind)) => Some(existing.merge(kind)),
+                            (existing, kind) => existing.or(kind),
+                        }
+                    }
+                    None => found.push((name, kind)),
+                }
+            }
+        }
+
+        // Fields that are null in the whole sample can hold anything
+        let mut columns: Vec<Column> = found.into_iter()
+            .map(|(name, kind)| Column { name, kind: kind.unwrap_or(ColumnType::Json) })
+            .collect();
+
+        columns.push(Column { name: EXTRA_COLUMN.to_string(), kind: ColumnType::Json });
+
+        let fields = columns.iter()
+            .map(|column| column.kind.parquet_type(&column.name).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+        let schema = Type::group_type_builder("schema").with_fields(fields).build()?;
+
+        let properties = WriterProperties::builder()
+            .set_compression(parquet::basic::Compression::SNAPPY)
+            .build();
+
+        self.writer = Some(SerializedFileWriter::new(self.buffer.clone(), Arc::new(schema), Arc::new(properties))?);
+        self.values = columns.iter().map(|column| (Values::new(column.kind), Vec::new())).collect();
+        self.columns = columns;
+
+        for doc in std::mem::take(&mut self.sample) {
+            self.push(doc).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn push(&mut self, doc: Document) -> Result<()> {
+        let mut fields = flatten(doc, self.options.nested);
+        let extra = self.columns.len() - 1;
+
+        for (index, column) in self.columns[..extra].iter().enumerate() {
+            let (values, levels) = &mut self.values[index];
+
+            let value = match fields.iter().position(|(name, _)| *name == column.name) {
+                Some(position) => fields.remove(position).1,
+                None => Bson::Null,
+            };
+
+            if matches!(value, Bson::Null | Bson::Undefined) {
+                levels.push(0);
+                continue;
+            }
+
+            match values.push(column.kind, value) {
+                Ok(_) => levels.push(1),
+                Err(value) => {
+                    levels.push(0);
+                    fields.push((column.name.clone(), value));
+                }
+            }
+        }
+
+        let (values, levels) = &mut self.values[extra];
+        match fields.is_empty() {
+            true => levels.push(0),
+            false => {
+                let extra_fields: Document = fields.into_iter().collect();
+                let _ = values.push(ColumnType::Json, Bson::Document(extra_fields));
+                levels.push(1);
+            }
+        }
+
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_SIZE {
+            self.write_row_group().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn write_row_group(&mut self) -> Result<()> {
+        let writer = match self.writer.as_mut() {
+            Some(res) => res,
+            None => return Ok(()),
+        };
+
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let mut row_group = writer.next_row_group()?;
+        let mut index = 0;
+
+        while let Some(mut column) = row_group.next_column()? {
+            let (values, levels) = &self.values[index];
+            values.write(column.untyped(), levels)?;
+            column.close()?;
+            index += 1;
+        }
+
+        row_group.close()?;
+
+        self.values = self.columns.iter().map(|column| (Values::new(column.kind), Vec::new())).collect();
+        self.rows = 0;
+
+        self.output.write_all(&self.buffer.take())?;
+        self.output.flush().await
+    }
+}
+
+/// Top-level fields of `doc`, embedded documents as `parent.child` with `Nested::Flatten`.
+fn flatten(doc: Document, nested: Nested) -> Vec<(String, Bson)> {
+    let mut fields = Vec::new();
+    flatten_into(doc, "", nested, &mut fields);
+    fields
+}
+
+fn flatten_into(doc: Document, prefix: &str, nested: Nested, fields: &mut Vec<(String, Bson)>) {
+    for (name, value) in doc {
+        let name = format!("{prefix}{name}");
+
+        match (value, nested) {
+            (Bson::Document(inner), Nested::Flatten) if !inner.is_empty() => flatten_into(inner, &format!("{name}."), nested, fields),
+            (value, _) => fields.push((name, value)),
+        }
+    }
+}
+
+/// Number of rows of a Parquet file and its first `limit` rows as documents.
+pub(crate) fn read_rows(data: Vec<u8>, limit: usize) -> Result<(u64, Vec<Document>)> {
+    let reader = SerializedFileReader::new(bytes::Bytes::from(data))?;
+    let rows = reader.metadata().file_metadata().num_rows() as u64;
+    let mut documents = Vec::new();
+
+    for row in reader.get_row_iter(None)?.take(limit) {
+        let value = row?.to_json_value();
+
+        match Bson::try_from(value) {
+            Ok(Bson::Document(doc)) => documents.push(doc),
+            Ok(_) => return Err(Error::Corrupt("Parquet row is not a document".to_string())),
+            Err(err) => return Err(Error::Corrupt(err.to_string())),
+        }
+    }
+
+    Ok((rows, documents))
+}
+
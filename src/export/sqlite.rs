@@ -0,0 +1,292 @@
+use bson::{Bson, RawDocumentBuf};
+use mongodb::IndexModel;
+use rusqlite::{params_from_iter, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+use time::OffsetDateTime;
+
+use crate::output::OutputFile;
+use crate::report::FileReport;
+use crate::{Error, Result};
+
+/// Name of the snapshot in the run, before the compression and encryption extensions.
+pub const SNAPSHOT_FILE: &str = "snapshot.sqlite";
+
+/// Rows inserted per transaction.
+const TRANSACTION_SIZE: u64 = 10_000;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// `sqlite` section of a connection.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SqliteOptions {
+    /// Top-level fields stored in their own columns, by collection (`name` or `db.name`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub promote: BTreeMap<String, Vec<String>>,
+}
+
+impl SqliteOptions {
+    fn promoted(&self, database: &str, collection: &str) -> Vec<String> {
+        let namespace = format!("{database}.{collection}");
+        let mut fields = Vec::new();
+
+        for (name, promoted) in &self.promote {
+            if *name == collection || *name == namespace {
+                for field in promoted {
+                    if field != "_id" && field != "document" && !fields.contains(field) {
+                        fields.push(field.clone());
+                    }
+                }
+            }
+        }
+
+        fields
+    }
+}
+
+/// Table the documents of the current collection go to.
+struct Table {
+    name: String,
+    promoted: Vec<String>,
+    insert: String,
+}
+
+/// SQLite file with one table per collection: `_id`, the document as relaxed Extended JSON
+/// and the promoted fields. It is built in a temporary file and copied into the run at the end.
+pub(crate) struct SqliteExport {
+    options: SqliteOptions,
+    path: PathBuf,
+    connection: Option<Connection>,
+    table: Option<Table>,
+    rows: u64,
+    pending: u64,
+}
+
+impl SqliteExport {
+    pub fn create(options: SqliteOptions) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "mongo_backuper-{}-{}.sqlite", std::process::id(), OffsetDateTime::now_utc().unix_timestamp_nanos()
+        ));
+
+        let connection = Connection::open(&path)?;
+        connection.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+
+        Ok(SqliteExport { options, path, connection: Some(connection), table: None, rows: 0, pending: 0 })
+    }
+
+    fn connection(&self) -> Result<&Connection> {
+        self.connection.as_ref().ok_or_else(|| Error::Storage("SQLite snapshot is already closed".to_string()))
+    }
+
+    /// Starts the table of a collection, the documents of it follow with `insert`.
+    pub fn begin(&mut self, database: &str, collection: &str) -> Result<()> {
+        self.end()?;
+
+        let name = format!("{database}.{collection}");
+        let promoted = self.options.promoted(database, collection);
+
+        let mut columns = vec!["\"_id\"".to_string(), "\"document\" TEXT NOT NULL".to_string()];
+        columns.extend(promoted.iter().map(|field| quote(field)));
+
+        let placeholders = vec!["?"; promoted.len() + 2].join(", ");
+        let insert = format!("INSERT INTO {} VALUES ({placeholders})", quote(&name));
+
+        self.connection()?.execute_batch(&format!(
+            "DROP TABLE IF EXISTS {table}; CREATE TABLE {table} ({}); BEGIN;",
+            columns.join(", "), table = quote(&name),
+        ))?;
+
+        self.table = Some(Table { name, promoted, insert });
+        Ok(())
+    }
+
+    pub fn insert(&mut self, doc: &RawDocumentBuf) -> Result<()> {
+        let doc = doc.to_document().map_err(|err| Error::Corrupt(err.to_string()))?;
+        let table = match &self.table {
+            Some(res) => res,
+            None => return Err(Error::Storage("SQLite snapshot has no table to insert into".to_string())),
+        };
+
+        let mut values = vec![
+            doc.get("_id").map(sql_value).unwrap_or(Value::Null),
+            Value::Text(Bson::Document(doc.clone()).into_relaxed_extjson().to_string()),
+        ];
+        values.extend(table.promoted.iter().map(|field| doc.get(field).map(sql_value).unwrap_or(Value::Null)));
+
+        let connection = match &self.connection {
+            Some(res) => res,
+            None => return Err(Error::Storage("SQLite snapshot is already closed".to_string())),
+        };
+        connection.prepare_cached(&table.insert)?.execute(params_from_iter(values))?;
+
+        self.rows += 1;
+        self.pending += 1;
+
+        if self.pending >= TRANSACTION_SIZE {
+            connection.execute_batch("COMMIT; BEGIN;")?;
+            self.pending = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Recreates the indexes of the collection that only use ascending or descending keys.
+    pub fn create_indexes(&mut self, indexes: &[IndexModel]) -> Result<()> {
+        let table = match &self.table {
+            Some(res) => res,
+            None => return Ok(()),
+        };
+
+        for index in indexes {
+            let options = index.options.as_ref();
+            let name = options.and_then(|options| options.name.clone())
+                .unwrap_or_else(|| index.keys.keys().cloned().collect::<Vec<_>>().join("_"));
+
+            let mut keys = Vec::new();
+            for (field, direction) in &index.keys {
+                let order = match direction {
+                    Bson::Int32(-1) | Bson::Int64(-1) => "DESC",
+                    Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) => "ASC",
+                    // text, 2dsphere, hashed, ...
+                    _ => break,
+                };
+
+                let column = match field.as_str() {
+                    "_id" => quote("_id"),
+                    field if table.promoted.iter().any(|promoted| promoted == field) => quote(field),
+                    field => format!("json_extract(\"document\", '{}')", json_path(field)),
+                };
+
+                keys.push(format!("{column} {order}"));
+            }
+
+            if keys.len() != index.keys.len() {
+                crate::logger::debug_string(format!("Index \"{name}\" of \"{}\" can not be recreated in SQLite", table.name));
+                continue;
+            }
+
+            // A unique index only holds for the documents its filter selects
+            let unique = name == "_id_" || options.is_some_and(|options| {
+                options.unique == Some(true) && options.sparse != Some(true) && options.partial_filter_expression.is_none()
+            });
+
+            let create = |unique: bool| format!(
+                "CREATE {}INDEX {} ON {} ({})",
+                if unique { "UNIQUE " } else { "" }, quote(&format!("{}.{name}", table.name)), quote(&table.name), keys.join(", "),
+            );
+
+            let connection = self.connection()?;
+            let mut result = connection.execute_batch(&create(unique));
+            if result.is_err() && unique {
+                result = connection.execute_batch(&create(false));
+            }
+
+            if let Err(err) = result {
+                crate::logger::debug_string(format!("Failed to recreate index \"{name}\" of \"{}\" > {err}", table.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Commits the rows of the current table.
+    fn end(&mut self) -> Result<()> {
+        if self.table.take().is_some() {
+            self.connection()?.execute_batch("COMMIT;")?;
+            self.pending = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Closes the database and writes it to `output`.
+    pub async fn finish(mut self, mut output: OutputFile) -> Result<FileReport> {
+        self.end()?;
+
+        if let Some(connection) = self.connection.take() {
+            connection.close().map_err(|(_, err)| err)?;
+        }
+
+        let mut file = std::fs::File::open(&self.path)?;
+        let mut chunk = vec![0; CHUNK_SIZE];
+
+        loop {
+            let read = std::io::Read::read(&mut file, &mut chunk)?;
+            if read == 0 {
+                break;
+            }
+
+            std::io::Write::write_all(&mut output, &chunk[..read])?;
+            output.flush().await?;
+        }
+
+        output.documents = self.rows;
+        output.finish(None, None).await
+    }
+}
+
+impl Drop for SqliteExport {
+    fn drop(&mut self) {
+        self.connection.take();
+        std::fs::remove_file(&self.path).unwrap_or_default();
+    }
+}
+
+/// Rows in all tables of a snapshot.
+pub(crate) fn count_rows(data: &[u8]) -> Result<u64> {
+    let path = std::env::temp_dir().join(format!(
+        "mongo_backuper-verify-{}-{}.sqlite", std::process::id(), OffsetDateTime::now_utc().unix_timestamp_nanos()
+    ));
+    std::fs::write(&path, data)?;
+
+    let result = (|| -> Result<u64> {
+        let connection = Connection::open(&path)?;
+        let tables = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut rows = 0;
+        for table in tables {
+            rows += connection.query_row(&format!("SELECT count(*) FROM {}", quote(&table)), [], |row| row.get::<_, i64>(0))? as u64;
+        }
+
+        Ok(rows)
+    })();
+
+    std::fs::remove_file(&path).unwrap_or_default();
+    result
+}
+
+/// Column value of a promoted field or of `_id`, anything without an SQL type is stored as JSON.
+fn sql_value(value: &Bson) -> Value {
+    match value {
+        Bson::Null | Bson::Undefined => Value::Null,
+        Bson::Boolean(value) => Value::Integer(*value as i64),
+        Bson::Int32(value) => Value::Integer(*value as i64),
+        Bson::Int64(value) => Value::Integer(*value),
+        Bson::Double(value) => Value::Real(*value),
+        Bson::String(value) | Bson::Symbol(value) => Value::Text(value.clone()),
+        Bson::ObjectId(value) => Value::Text(value.to_hex()),
+        Bson::Decimal128(value) => Value::Text(value.to_string()),
+        Bson::DateTime(value) => Value::Text(value.try_to_rfc3339_string().unwrap_or_else(|_| value.to_string())),
+        value => Value::Text(value.clone().into_relaxed_extjson().to_string()),
+    }
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// JSON path of a dotted MongoDB field for `json_extract`, written the way a query would
+/// spell it so that SQLite can use the index.
+fn json_path(field: &str) -> String {
+    let path: String = field.split('.')
+        .map(|part| match part.chars().all(|char| char.is_ascii_alphanumeric() || char == '_') {
+            true => format!(".{part}"),
+            false => format!(".\"{}\"", part.replace('"', "\\\"")),
+        })
+        .collect();
+
+    format!("${path}").replace('\'', "''")
+}
+
@@ -1,48 +1,41 @@
-use std::{process, time::Duration};
+use std::{io::IsTerminal, process, time::Duration};
 
 use time::OffsetDateTime;
 
 pub fn get_date() -> String {
     let date = OffsetDateTime::now_local().unwrap_or(OffsetDateTime::now_utc());
-    return format!("{:0width$}.{:0width$}.{:0width$} {:0width$}:{:0width$}:{:0width$}",
+    format!("{:0width$}.{:0width$}.{:0width$} {:0width$}:{:0width$}:{:0width$}",
         date.day(), date.month() as usize, date.year(), date.hour(), date.minute(), date.second(),
-        width = 2);
+        width = 2)
 }
 
 pub fn get_date_file() -> String {
     let date = OffsetDateTime::now_local().unwrap_or(OffsetDateTime::now_utc());
-    return format!("{:0width$}.{:0width$}.{:0width$} {:0width$}-{:0width$}",
+    format!("{:0width$}.{:0width$}.{:0width$} {:0width$}-{:0width$}",
         date.year(), date.month() as usize, date.day(), date.hour(), date.minute(),
-        width = 2);
+        width = 2)
 }
 
-pub fn close_proc() {
-    let rt = match tokio::runtime::Runtime::new() {
-        Ok(res) => res,
-        Err(err) => {
-            crate::logger::error_string(format!("Failed to create tokio runtime in close_proc(): {err}"));
-            return;
-        }
-    };
-
-    rt.block_on(async {
+/// Exits with `code`. A console window that was opened for the app (stdin is a terminal on
+/// Windows) stays open for 5 seconds first, so that the last messages can be read.
+pub fn close_proc(code: i32) -> ! {
+    if cfg!(target_os = "windows") && std::io::stdin().is_terminal() {
         crate::logger::warn("Window will be closed after 5 seconds");
-        tokio::time::sleep(Duration::from_secs(5)).await;
-    });
+        std::thread::sleep(Duration::from_secs(5));
+    }
 
-    process::exit(0x0100);
+    process::exit(code);
 }
 
-pub fn read_line() -> String {
+/// Next line typed into the terminal, `None` once stdin is closed.
+pub fn read_line() -> Option<String> {
     let mut line = String::new();
     match std::io::stdin().read_line(&mut line) {
-        Ok(_) => {
-            line = line.replace('\n', "").trim().to_lowercase();
-        }
+        Ok(0) => None,
+        Ok(_) => Some(line.replace('\n', "").trim().to_string()),
         Err(err) => {
             crate::logger::error_string(format!("Couldn't read the line: {err}"));
+            None
         }
-    };
-
-    return line;
+    }
 }
\ No newline at end of file
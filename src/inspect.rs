@@ -0,0 +1,93 @@
+use bson::Document;
+
+use crate::archive::{ArchiveParser, Entry};
+use crate::crypto::Secret;
+use crate::job::Format;
+use crate::reader::{DocumentSplitter, ObjectStream};
+use crate::report::load_manifest;
+use crate::sink::BackupSink;
+use crate::{Error, Result};
+
+/// First documents of one collection of a run.
+#[derive(Clone, Debug)]
+pub struct CollectionSample {
+    pub database: String,
+    pub collection: String,
+    pub documents: Vec<Document>,
+}
+
+/// Reads up to `limit` documents of every collection of `run`, `secret` is needed for encrypted runs.
+pub async fn inspect(sink: &dyn BackupSink, run: &str, secret: Option<&Secret>, limit: usize) -> Result<Vec<CollectionSample>> {
+    let manifest = load_manifest(sink, run).await?;
+    let key = manifest.key(secret)?;
+    let mut samples = Vec::new();
+
+    for file in manifest.document_files() {
+        let mut stream = ObjectStream::open(sink, run, &file.path, manifest.compression, key.as_ref()).await?;
+
+        if manifest.archive {
+            let mut parser = ArchiveParser::default();
+
+            while let Some(chunk) = stream.next_chunk().await? {
+                parser.push(&chunk);
+
+                while let Some(entry) = parser.next_entry()? {
+                    match entry {
+                        Entry::Namespace(database, collection) => {
+                            samples.push(CollectionSample { database, collection, documents: Vec::new() });
+                        }
+                        Entry::Document(doc) => match samples.last_mut() {
+                            Some(sample) if sample.documents.len() < limit => sample.documents.push(parse(&doc)?),
+                            Some(_) => {}
+                            None => return Err(Error::Corrupt("Document before namespace in archive".to_string())),
+                        },
+                        Entry::End => {}
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        let mut sample = CollectionSample {
+            database: file.database.clone().unwrap_or_default(),
+            collection: file.collection.clone().unwrap_or_default(),
+            documents: Vec::new(),
+        };
+
+        // The rows can only be read once the footer at the end of the file is there
+        if file.format == Format::Parquet {
+            let mut data = Vec::new();
+            while let Some(chunk) = stream.next_chunk().await? {
+                data.extend_from_slice(&chunk);
+            }
+
+            sample.documents = crate::export::read_rows(data, limit)?.1;
+            samples.push(sample);
+            continue;
+        }
+        let mut splitter = DocumentSplitter::new(file.format);
+
+        while sample.documents.len() < limit {
+            let chunk = match stream.next_chunk().await? {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            splitter.push(&chunk);
+
+            while let Some(doc) = splitter.next_document()? {
+                if sample.documents.len() < limit {
+                    sample.documents.push(parse(&doc)?);
+                }
+            }
+        }
+
+        samples.push(sample);
+    }
+
+    Ok(samples)
+}
+
+fn parse(doc: &[u8]) -> Result<Document> {
+    Document::from_reader(doc).map_err(|err| Error::Corrupt(err.to_string()))
+}
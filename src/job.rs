@@ -0,0 +1,886 @@
+use bson::{doc, RawDocumentBuf};
+use futures_util::StreamExt;
+use mongodb::{Client, Collection, Database, IndexModel};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io::Write, path::PathBuf, sync::Arc, time::Duration};
+use time::OffsetDateTime;
+
+use crate::crypto::{Encryption, Key};
+use crate::export::{ParquetOptions, ParquetWriter, SqliteExport, SqliteOptions, SNAPSHOT_FILE};
+use crate::mirror::Mirror;
+use crate::output::OutputFile;
+use crate::report::{load_manifest, BackupReport, EncryptionReport, FileReport};
+use crate::secrets::ConnectionUrl;
+use crate::signing::SigningKey;
+use crate::sink::{BackupSink, Dedup, LocalFs, ObjectWriter};
+use crate::{Error, Result};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+        }
+    }
+}
+
+/// How the documents of a collection are written: BSON like `mongodump`, Extended JSON
+/// with one document per line, or both BSON and relaxed Extended JSON.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Bson,
+    Canonical,
+    Relaxed,
+    Both,
+    /// Only a Parquet export, which can not be restored.
+    Parquet,
+    /// Only the SQLite snapshot of the run, which can not be restored.
+    Sqlite,
+}
+
+impl Format {
+    /// Formats of the files written for every collection.
+    pub fn files(&self) -> &'static [Format] {
+        match self {
+            Format::Bson => &[Format::Bson],
+            Format::Canonical => &[Format::Canonical],
+            Format::Relaxed => &[Format::Relaxed],
+            Format::Both => &[Format::Bson, Format::Relaxed],
+            Format::Parquet => &[Format::Parquet],
+            Format::Sqlite => &[],
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Bson | Format::Both => ".bson",
+            Format::Canonical | Format::Relaxed => ".ndjson",
+            Format::Parquet => ".parquet",
+            Format::Sqlite => ".sqlite",
+        }
+    }
+
+    pub fn is_bson(&self) -> bool {
+        *self == Format::Bson
+    }
+
+    /// Encodes one document as a line of Extended JSON.
+    pub fn json_line(&self, doc: &RawDocumentBuf) -> Result<Vec<u8>> {
+        let doc = bson::Bson::Document(doc.to_document().map_err(|err| Error::Corrupt(err.to_string()))?);
+
+        let value = match self {
+            Format::Canonical => doc.into_canonical_extjson(),
+            _ => doc.into_relaxed_extjson(),
+        };
+
+        let mut line = serde_json::to_vec(&value)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+/// Selects which databases and collections are dumped. Empty include lists
+/// mean "everything"; collections can be given as `name` or `db.name`.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub databases: Vec<String>,
+    pub exclude_databases: Vec<String>,
+    pub collections: Vec<String>,
+    pub exclude_collections: Vec<String>,
+}
+
+impl Filter {
+    pub fn allows_database(&self, database: &str) -> bool {
+        if database == "config" || database == "local" {
+            return false;
+        }
+
+        if !self.databases.is_empty() && !self.databases.iter().any(|name| name == database) {
+            return false;
+        }
+
+        !self.exclude_databases.iter().any(|name| name == database)
+    }
+
+    pub fn allows_collection(&self, database: &str, collection: &str) -> bool {
+        let namespace = format!("{database}.{collection}");
+        let matches = |name: &String| name == collection || *name == namespace;
+
+        if !self.collections.is_empty() && !self.collections.iter().any(matches) {
+            return false;
+        }
+
+        !self.exclude_collections.iter().any(matches)
+    }
+}
+
+/// One place a run is copied to, with its own retention.
+#[derive(Clone)]
+pub struct Destination {
+    pub name: String,
+    pub sink: Arc<dyn BackupSink>,
+    /// Overrides the retention of the job for this destination.
+    pub retention: Option<Duration>,
+    /// Colder storage that runs are moved to once they get old.
+    pub tier: Option<Tier>,
+}
+
+impl Destination {
+    pub fn new(name: impl Into<String>, sink: Arc<dyn BackupSink>) -> Self {
+        Destination { name: name.into(), sink, retention: None, tier: None }
+    }
+
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn tier(mut self, tier: Tier) -> Self {
+        self.tier = Some(tier);
+        self
+    }
+}
+
+/// Storage that receives runs older than `move_after` from a destination
+/// and deletes them after its own `retention`.
+#[derive(Clone)]
+pub struct Tier {
+    pub name: String,
+    pub sink: Arc<dyn BackupSink>,
+    pub move_after: Duration,
+    pub retention: Option<Duration>,
+}
+
+impl Tier {
+    pub fn new(name: impl Into<String>, sink: Arc<dyn BackupSink>, move_after: Duration) -> Self {
+        Tier { name: name.into(), sink, move_after, retention: None }
+    }
+
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+}
+
+/// A single backup target: where to read from, what to dump and where to put it.
+#[derive(Clone)]
+pub struct BackupJob {
+    name: String,
+    url: ConnectionUrl,
+    destinations: Vec<Destination>,
+    filter: Filter,
+    compression: Compression,
+    format: Format,
+    parquet: Option<ParquetOptions>,
+    sqlite: Option<SqliteOptions>,
+    archive: bool,
+    retention: Option<Duration>,
+    encryption: Option<Encryption>,
+    signing: Option<SigningKey>,
+    link_unchanged: bool,
+}
+
+pub struct BackupJobBuilder {
+    name: String,
+    url: ConnectionUrl,
+    destinations: Vec<Destination>,
+    tier: Option<Tier>,
+    filter: Filter,
+    compression: Compression,
+    format: Format,
+    parquet: Option<ParquetOptions>,
+    sqlite: Option<SqliteOptions>,
+    archive: bool,
+    retention: Option<Duration>,
+    encryption: Option<Encryption>,
+    signing: Option<SigningKey>,
+    dedup: bool,
+    link_unchanged: bool,
+}
+
+impl BackupJobBuilder {
+    /// Storage that receives the runs, replaces the destinations added before.
+    /// Defaults to `LocalFs` at `paths::output()/<name>`.
+    pub fn sink(mut self, sink: Arc<dyn BackupSink>) -> Self {
+        self.destinations = vec![Destination::new("default", sink)];
+        self
+    }
+
+    /// Adds one more place every run is copied to.
+    pub fn destination(mut self, destination: Destination) -> Self {
+        self.destinations.push(destination);
+        self
+    }
+
+    /// Colder storage for the destinations that have no tier of their own.
+    pub fn tier(mut self, tier: Tier) -> Self {
+        self.tier = Some(tier);
+        self
+    }
+
+    /// Shorthand for a `LocalFs` sink rooted at `path`.
+    pub fn output(self, path: impl Into<PathBuf>) -> Self {
+        self.sink(Arc::new(LocalFs::new(path)))
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn include_database(mut self, database: impl Into<String>) -> Self {
+        self.filter.databases.push(database.into());
+        self
+    }
+
+    pub fn exclude_database(mut self, database: impl Into<String>) -> Self {
+        self.filter.exclude_databases.push(database.into());
+        self
+    }
+
+    pub fn include_collection(mut self, collection: impl Into<String>) -> Self {
+        self.filter.collections.push(collection.into());
+        self
+    }
+
+    pub fn exclude_collection(mut self, collection: impl Into<String>) -> Self {
+        self.filter.exclude_collections.push(collection.into());
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypt every file of the run with AES-256-GCM, with a `Key` or to age recipients.
+    pub fn encryption(mut self, encryption: impl Into<Encryption>) -> Self {
+        self.encryption = Some(encryption.into());
+        self
+    }
+
+    /// Sign the manifest of every run with an Ed25519 key, written as `manifest.sig`.
+    pub fn signing(mut self, key: SigningKey) -> Self {
+        self.signing = Some(key);
+        self
+    }
+
+    /// Format of the collection files, see `Format`. Archives are always BSON.
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Also export every collection as a Parquet file, with `Format::Parquet` instead of the dump.
+    pub fn parquet(mut self, options: ParquetOptions) -> Self {
+        self.parquet = Some(options);
+        self
+    }
+
+    /// Also write a SQLite snapshot of the run, with `Format::Sqlite` instead of the dump.
+    pub fn sqlite(mut self, options: SqliteOptions) -> Self {
+        self.sqlite = Some(options);
+        self
+    }
+
+    /// Write the whole run into a single `backup.archive` stream instead of one file per collection.
+    pub fn archive(mut self, archive: bool) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    /// Store the destinations as deduplicated repositories (see `Dedup`): files are split into
+    /// chunks that are stored once, compression then applies to every chunk. Runs moved to a
+    /// tier are stored in full.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Compare the `dbHash` of every collection with the previous run and link its file
+    /// instead of dumping it again when nothing changed. Storage without links dumps it anyway.
+    pub fn link_unchanged(mut self, link_unchanged: bool) -> Self {
+        self.link_unchanged = link_unchanged;
+        self
+    }
+
+    /// Remove runs older than `retention` before every backup (the newest run is always kept).
+    /// Applies to every destination without its own retention.
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn build(self) -> Result<BackupJob> {
+        if self.name.trim().is_empty() {
+            return Err(Error::Config("backup name can not be empty".to_string()));
+        }
+
+        if self.url.is_empty() {
+            return Err(Error::Config(format!("connection url of \"{}\" can not be empty", self.name)));
+        }
+
+        let name = self.name;
+        let mut destinations = self.destinations;
+
+        if destinations.is_empty() {
+            let sink = Arc::new(LocalFs::new(crate::paths::output().join(&name)));
+            destinations.push(Destination::new("default", sink));
+        }
+
+        if let Some(tier) = self.tier {
+            for destination in destinations.iter_mut().filter(|destination| destination.tier.is_none()) {
+                destination.tier = Some(tier.clone());
+            }
+        }
+
+        for (index, destination) in destinations.iter().enumerate() {
+            if destinations[..index].iter().any(|other| other.name == destination.name) {
+                return Err(Error::Config(format!("destination \"{}\" of \"{name}\" is listed twice", destination.name)));
+            }
+
+            if let Some(tier) = &destination.tier {
+                if tier.sink.describe() == destination.sink.describe() {
+                    return Err(Error::Config(format!("tier \"{}\" of \"{name}\" is the same storage as its destination", tier.name)));
+                }
+            }
+        }
+
+        if self.archive && (!self.format.is_bson() || self.parquet.is_some()) {
+            return Err(Error::Config(format!("archive of \"{name}\" can only be written as BSON")));
+        }
+
+        // A run encrypted to recipients has a key of its own, no file of it can be reused
+        if self.link_unchanged && matches!(self.encryption, Some(Encryption::Recipients(_))) {
+            return Err(Error::Config(format!("\"{name}\" can not link unchanged collections when it is encrypted to recipients")));
+        }
+
+        let mut compression = self.compression;
+
+        if self.dedup {
+            if self.encryption.is_some() {
+                return Err(Error::Config(format!("\"{name}\" can not be deduplicated and encrypted at the same time")));
+            }
+
+            for destination in destinations.iter_mut() {
+                destination.sink = Arc::new(Dedup::new(destination.sink.clone(), compression));
+            }
+
+            // Chunks are compressed one by one, a compressed stream would not deduplicate
+            compression = Compression::None;
+        }
+
+        Ok(BackupJob {
+            name,
+            url: self.url,
+            destinations,
+            filter: self.filter,
+            compression,
+            format: self.format,
+            parquet: self.parquet,
+            sqlite: self.sqlite,
+            archive: self.archive,
+            retention: self.retention,
+            encryption: self.encryption,
+            signing: self.signing,
+            link_unchanged: self.link_unchanged,
+        })
+    }
+}
+
+impl BackupJob {
+    pub fn builder(name: impl Into<String>, url: impl Into<ConnectionUrl>) -> BackupJobBuilder {
+        BackupJobBuilder {
+            name: name.into(),
+            url: url.into(),
+            destinations: Vec::new(),
+            tier: None,
+            filter: Filter::default(),
+            compression: Compression::None,
+            format: Format::Bson,
+            parquet: None,
+            sqlite: None,
+            archive: false,
+            retention: None,
+            encryption: None,
+            signing: None,
+            dedup: false,
+            link_unchanged: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Connection string as configured, `resolve` it to connect.
+    pub fn url(&self) -> &ConnectionUrl {
+        &self.url
+    }
+
+    pub fn destinations(&self) -> &[Destination] {
+        &self.destinations
+    }
+
+    pub fn encryption(&self) -> Option<&Encryption> {
+        self.encryption.as_ref()
+    }
+
+    pub fn signing(&self) -> Option<&SigningKey> {
+        self.signing.as_ref()
+    }
+
+    /// Sink of the first destination.
+    pub fn sink(&self) -> &Arc<dyn BackupSink> {
+        &self.destinations[0].sink
+    }
+
+    /// Moves and deletes old runs of every destination as their retention and tier say.
+    /// Returns `false` when a destination failed, the others are still pruned.
+    pub async fn prune(&self) -> bool {
+        let mut ok = true;
+
+        for destination in &self.destinations {
+            ok &= crate::retention::sweep(destination, &self.name, self.retention).await;
+        }
+
+        ok
+    }
+
+    /// Dump every selected collection once, copy it to every destination and write the manifest of the run.
+    pub async fn run_once(&self) -> Result<BackupReport> {
+        self.prune().await;
+
+        crate::logger::info_string(format!("Backing up the collection \"{}\" has been started", &self.name));
+
+        let run = crate::exts::get_date_file();
+
+        let (key, encryption) = match &self.encryption {
+            Some(encryption) => {
+                let (key, report) = encryption.run_key()?;
+                (Some(key), Some(report))
+            }
+            None => (None, None),
+        };
+
+        let previous = match self.link_unchanged && !self.archive {
+            true => self.previous_run(&run, encryption.as_ref()).await,
+            false => None,
+        };
+
+        let mirror = Mirror::new(&self.destinations, &run);
+        mirror.prepare().await?;
+
+        let mut report = BackupReport {
+            name: self.name.clone(),
+            run: run.clone(),
+            started_at: OffsetDateTime::now_utc().unix_timestamp(),
+            finished_at: 0,
+            compression: self.compression,
+            format: self.format,
+            archive: self.archive,
+            files: Vec::new(),
+            errors: Vec::new(),
+            copies: Vec::new(),
+            encryption,
+        };
+
+        let client = Client::with_uri_str(self.url.resolve()?).await?;
+        let selected = self.selected_collections(&client, &mut report.errors).await?;
+
+        let mut sqlite = match (&self.sqlite, self.format) {
+            (Some(options), _) => Some(SqliteExport::create(options.clone())?),
+            (None, Format::Sqlite) => Some(SqliteExport::create(SqliteOptions::default())?),
+            (None, _) => None,
+        };
+
+        let mut archive = match self.archive {
+            true => {
+                let path = format!("backup.archive{}", self.extension());
+                let mut file = OutputFile::new(path.clone(), mirror.create_object(&path).await?, self.compression, key.as_ref());
+                crate::archive::write_header(&mut file)?;
+                Some(file)
+            }
+            false => None,
+        };
+
+        for (db, collections) in selected {
+            let db_name = db.name().to_string();
+
+            crate::logger::debug_string(format!("Creating Backup of \"{db_name}\" in \"{}\"", &self.name));
+
+            let fingerprints = match self.link_unchanged && archive.is_none() {
+                true => collection_hashes(&db, collections.clone()).await,
+                false => HashMap::new(),
+            };
+
+            for collection_name in collections {
+                let result = match archive.as_mut() {
+                    Some(file) => {
+                        dump_into_archive(&db, &collection_name, file, sqlite.as_mut()).await
+                    }
+                    None => {
+                        let fingerprint = fingerprints.get(&collection_name).cloned();
+                        self.backup_collection(&db.collection(&collection_name), &mirror, key.as_ref(), previous.as_ref(), fingerprint, sqlite.as_mut()).await
+                            .map(|files| report.files.extend(files))
+                    }
+                };
+
+                if let Err(err) = result {
+                    crate::logger::warn_string(
+                        format!("Failed to back up collection \"{db_name}.{collection_name}\" > {err}")
+                    );
+                    report.errors.push(format!("{db_name}.{collection_name}: {err}"));
+                }
+            }
+        }
+
+        if let Some(mut file) = archive {
+            crate::archive::write_end(&mut file)?;
+            report.files.push(file.finish(None, None).await?);
+        }
+
+        if let Some(sqlite) = sqlite {
+            let path = format!("{SNAPSHOT_FILE}{}", self.extension());
+            let output = OutputFile::new(path.clone(), mirror.create_object(&path).await?, self.compression, key.as_ref());
+
+            match sqlite.finish(output).await {
+                Ok(file) => report.files.push(FileReport { format: Format::Sqlite, ..file }),
+                Err(err) => {
+                    crate::logger::warn_string(format!("Failed to write the SQLite snapshot of \"{}\" > {err}", self.name));
+                    report.errors.push(format!("{SNAPSHOT_FILE}: {err}"));
+                }
+            }
+        }
+
+        report.finished_at = OffsetDateTime::now_utc().unix_timestamp();
+        // Every destination that has all files, failures while finishing only reach the returned report
+        report.copies = mirror.copies();
+
+        let manifest = serde_json::to_vec_pretty(&report)?;
+        let signature = match &self.signing {
+            Some(key) => Some(crate::signing::sign(key, &manifest)?),
+            None => None,
+        };
+
+        let finished = mirror.finish(&manifest, signature.as_deref()).await;
+        report.copies = mirror.copies();
+        finished?;
+
+        crate::logger::info_string(format!("Backup of the collection \"{}\" completed", &self.name));
+
+        Ok(report)
+    }
+
+    /// Writes every selected collection as a plain or gzip compressed archive stream to `writer`,
+    /// without a run or manifest. The stream is never encrypted and the exports are left out.
+    /// A collection that fails ends the stream, so that it is never restored partly.
+    pub async fn stream_archive(&self, writer: Box<dyn ObjectWriter>) -> Result<FileReport> {
+        crate::logger::info_string(format!("Streaming the backup of \"{}\" has been started", &self.name));
+
+        if self.encryption.is_some() {
+            crate::logger::warn_string(format!("The archive stream of \"{}\" is not encrypted", &self.name));
+        }
+
+        let client = Client::with_uri_str(self.url.resolve()?).await?;
+
+        let mut errors = Vec::new();
+        let selected = self.selected_collections(&client, &mut errors).await?;
+        if let Some(error) = errors.first() {
+            return Err(Error::Storage(format!("Failed to list the collections of {error}")));
+        }
+
+        let mut file = OutputFile::new("backup.archive".to_string(), writer, self.compression, None);
+        crate::archive::write_header(&mut file)?;
+
+        for (db, collections) in selected {
+            for collection_name in collections {
+                if let Err(err) = dump_into_archive(&db, &collection_name, &mut file, None).await {
+                    return Err(Error::Storage(format!("Failed to back up collection \"{}.{collection_name}\" > {err}", db.name())));
+                }
+            }
+        }
+
+        crate::archive::write_end(&mut file)?;
+        let report = file.finish(None, None).await?;
+
+        crate::logger::info_string(format!("Streamed {} documents of \"{}\" ({} bytes)", report.documents, &self.name, report.bytes));
+
+        Ok(report)
+    }
+
+    /// Databases and their collections that pass the filter. A database whose collections
+    /// can not be listed is left out and added to `errors`.
+    async fn selected_collections(&self, client: &Client, errors: &mut Vec<String>) -> Result<Vec<(Database, Vec<String>)>> {
+        let databases = client.list_database_names(None, None).await?;
+        let mut selected = Vec::new();
+
+        for db_name in databases {
+            if !self.filter.allows_database(&db_name) {
+                continue;
+            }
+
+            let db = client.database(&db_name);
+
+            let collections = match db.list_collection_names(None).await {
+                Ok(res) => res,
+                Err(err) => {
+                    crate::logger::error_string(err.to_string());
+                    errors.push(format!("{db_name}: {err}"));
+                    continue;
+                }
+            };
+
+            let collections = collections.into_iter()
+                .filter(|collection| self.filter.allows_collection(&db_name, collection))
+                .collect();
+
+            selected.push((db, collections));
+        }
+
+        Ok(selected)
+    }
+
+    fn extension(&self) -> String {
+        match self.encryption {
+            Some(_) => format!("{}{}", self.compression.extension(), crate::crypto::EXTENSION),
+            None => self.compression.extension().to_string(),
+        }
+    }
+
+    /// Newest earlier run of the first destination whose files can be linked into a run encrypted with `encryption`.
+    async fn previous_run(&self, run: &str, encryption: Option<&EncryptionReport>) -> Option<BackupReport> {
+        let sink = self.sink().as_ref();
+        let mut runs = sink.list_runs().await.ok()?;
+        runs.sort();
+
+        for previous in runs.iter().rev().filter(|previous| *previous != run && !previous.ends_with(crate::rekey::STAGING)) {
+            let manifest = match load_manifest(sink, previous).await {
+                Ok(res) => res,
+                Err(_) => continue,
+            };
+
+            let key_id = |encryption: Option<&EncryptionReport>| encryption.map(|encryption| encryption.key_id.clone());
+            if manifest.compression != self.compression || manifest.archive || key_id(manifest.encryption.as_ref()) != key_id(encryption) {
+                return None;
+            }
+
+            return Some(manifest);
+        }
+
+        None
+    }
+
+    /// Links the files of the collection from `previous` when its fingerprint did not change, dumps it otherwise.
+    async fn backup_collection(&self, collection: &Collection<RawDocumentBuf>, mirror: &Mirror<'_>, key: Option<&Key>, previous: Option<&BackupReport>, fingerprint: Option<String>, sqlite: Option<&mut SqliteExport>) -> Result<Vec<FileReport>> {
+        let (db_name, collection_name) = (collection.namespace().db, collection.name());
+        let paths: Vec<String> = self.file_formats().into_iter()
+            .map(|format| self.collection_path(&db_name, collection_name, format))
+            .collect();
+
+        let unchanged = previous.zip(fingerprint.as_ref()).and_then(|(previous, fingerprint)| {
+            let files = paths.iter()
+                .map(|path| previous.files.iter().find(|file| file.path == *path && file.fingerprint.as_ref() == Some(fingerprint)))
+                .collect::<Option<Vec<_>>>()?;
+            Some((previous, files))
+        });
+
+        if let Some((previous, files)) = unchanged {
+            let mut linked = true;
+
+            for file in &files {
+                if !mirror.link_object(&previous.run, &file.path).await {
+                    linked = false;
+                    break;
+                }
+            }
+
+            if linked {
+                crate::logger::debug_string(format!("\"{db_name}.{collection_name}\" is unchanged, linked from \"{}\"", previous.run));
+
+                // The snapshot has no previous copy to link to
+                if let Some(sqlite) = sqlite {
+                    let mut cursor = collection.find(None, None).await?;
+
+                    sqlite.begin(&db_name, collection_name)?;
+                    while let Some(doc) = cursor.next().await {
+                        sqlite.insert(&doc?)?;
+                    }
+                    sqlite.create_indexes(&list_indexes(collection).await)?;
+                }
+
+                return Ok(files.into_iter().cloned().collect());
+            }
+        }
+
+        let mut files = self.dump_collection(collection, mirror, key, sqlite).await?;
+        for file in files.iter_mut() {
+            file.fingerprint = fingerprint.clone();
+        }
+
+        Ok(files)
+    }
+
+    /// Formats of the files of every collection, the dump first.
+    fn file_formats(&self) -> Vec<Format> {
+        let mut formats = self.format.files().to_vec();
+
+        if self.parquet.is_some() && !formats.contains(&Format::Parquet) {
+            formats.push(Format::Parquet);
+        }
+
+        formats
+    }
+
+    fn collection_path(&self, db_name: &str, collection_name: &str, format: Format) -> String {
+        format!("{db_name}/{collection_name}{}{}", format.extension(), self.extension())
+    }
+
+    /// Writes the documents of the collection once for every file format.
+    async fn dump_collection(&self, collection: &Collection<RawDocumentBuf>, mirror: &Mirror<'_>, key: Option<&Key>, mut sqlite: Option<&mut SqliteExport>) -> Result<Vec<FileReport>> {
+        let (db_name, collection_name) = (collection.namespace().db, collection.name());
+        let mut cursor = collection.find(None, None).await?;
+
+        if let Some(sqlite) = sqlite.as_mut() {
+            sqlite.begin(&db_name, collection_name)?;
+        }
+
+        let mut outputs = Vec::new();
+        for format in self.file_formats() {
+            let path = self.collection_path(&db_name, collection_name, format);
+            let file = OutputFile::new(path.clone(), mirror.create_object(&path).await?, self.compression, key);
+
+            outputs.push(match format {
+                Format::Parquet => CollectionOutput::Parquet(Box::new(ParquetWriter::new(file, self.parquet.clone().unwrap_or_default()))),
+                _ => CollectionOutput::File(format, Box::new(file)),
+            });
+        }
+
+        while let Some(doc) = cursor.next().await {
+            let doc = doc?;
+
+            for output in outputs.iter_mut() {
+                match output {
+                    CollectionOutput::File(format, file) => {
+                        let file = file.as_mut();
+                        match format {
+                            Format::Bson => file.write_all(doc.as_bytes())?,
+                            _ => file.write_all(&format.json_line(&doc)?)?,
+                        }
+                        file.documents += 1;
+                        file.flush().await?;
+                    }
+                    CollectionOutput::Parquet(writer) => writer.write(&doc).await?,
+                }
+            }
+
+            if let Some(sqlite) = sqlite.as_mut() {
+                sqlite.insert(&doc)?;
+            }
+        }
+
+        if let Some(sqlite) = sqlite {
+            sqlite.create_indexes(&list_indexes(collection).await)?;
+        }
+
+        let (database, collection) = (Some(db_name.clone()), Some(collection_name.to_string()));
+        let mut files = Vec::new();
+
+        for output in outputs {
+            let (format, report) = match output {
+                CollectionOutput::File(format, file) => (format, file.finish(database.clone(), collection.clone()).await?),
+                CollectionOutput::Parquet(writer) => (Format::Parquet, writer.finish(database.clone(), collection.clone()).await?),
+            };
+
+            files.push(FileReport { format, ..report });
+        }
+
+        Ok(files)
+    }
+}
+
+/// One file a collection is written to.
+enum CollectionOutput {
+    File(Format, Box<OutputFile>),
+    Parquet(Box<ParquetWriter>),
+}
+
+/// `dbHash` of `collections`, empty when the server does not support it.
+async fn collection_hashes(db: &Database, collections: Vec<String>) -> HashMap<String, String> {
+    let result = match db.run_command(doc! { "dbHash": 1, "collections": collections }, None).await {
+        Ok(res) => res,
+        Err(err) => {
+            crate::logger::debug_string(format!("dbHash of \"{}\" failed, every collection is dumped > {err}", db.name()));
+            return HashMap::new();
+        }
+    };
+
+    match result.get_document("collections") {
+        Ok(hashes) => hashes.iter()
+            .filter_map(|(collection, hash)| hash.as_str().map(|hash| (collection.clone(), hash.to_string())))
+            .collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+async fn dump_into_archive(db: &Database, collection_name: &str, file: &mut OutputFile, mut sqlite: Option<&mut SqliteExport>) -> Result<()> {
+    let collection = db.collection::<RawDocumentBuf>(collection_name);
+    let mut cursor = collection.find(None, None).await?;
+
+    crate::archive::write_namespace(file, db.name(), collection_name)?;
+
+    if let Some(sqlite) = sqlite.as_mut() {
+        sqlite.begin(db.name(), collection_name)?;
+    }
+
+    while let Some(doc) = cursor.next().await {
+        let doc = doc?;
+        crate::archive::write_document(file, doc.as_bytes())?;
+        file.documents += 1;
+        file.flush().await?;
+
+        if let Some(sqlite) = sqlite.as_mut() {
+            sqlite.insert(&doc)?;
+        }
+    }
+
+    if let Some(sqlite) = sqlite {
+        sqlite.create_indexes(&list_indexes(&collection).await)?;
+    }
+
+    Ok(())
+}
+
+/// Index specs of a collection for the SQLite snapshot, empty when they can not be listed.
+async fn list_indexes(collection: &Collection<RawDocumentBuf>) -> Vec<IndexModel> {
+    let mut cursor = match collection.list_indexes(None).await {
+        Ok(res) => res,
+        Err(err) => {
+            crate::logger::debug_string(format!("Failed to list the indexes of \"{}\" > {err}", collection.namespace()));
+            return Vec::new();
+        }
+    };
+
+    let mut indexes = Vec::new();
+    while let Some(index) = cursor.next().await {
+        match index {
+            Ok(index) => indexes.push(index),
+            Err(err) => {
+                crate::logger::debug_string(format!("Failed to list the indexes of \"{}\" > {err}", collection.namespace()));
+                break;
+            }
+        }
+    }
+
+    indexes
+}
@@ -0,0 +1,41 @@
+pub mod archive;
+pub mod backuper;
+pub mod config;
+pub mod crypto;
+pub mod export;
+pub mod exts;
+pub mod inspect;
+pub mod job;
+pub mod logger;
+pub mod paths;
+pub mod pipe;
+pub mod reader;
+pub mod rekey;
+pub mod reload;
+pub mod report;
+pub mod restore;
+pub mod retention;
+pub mod scheduler;
+pub mod secrets;
+pub mod signing;
+pub mod sink;
+pub mod validate;
+pub mod verify;
+
+mod error;
+mod mirror;
+mod output;
+
+pub use error::{Error, Result};
+pub use job::{BackupJob, BackupJobBuilder, Compression, Destination, Filter, Format, Tier};
+pub use report::BackupReport;
+pub use scheduler::Scheduler;
+pub use sink::{BackupSink, LocalFs};
+
+/// Default directory of the config and the backups, used when it exists (always on Windows), see `paths`.
+#[cfg(not(target_os = "windows"))]
+pub const DIRECTORY: &str = "/MongoBackups";
+
+#[cfg(target_os = "windows")]
+pub const DIRECTORY: &str = "C:\\MongoBackups";
+
@@ -1,35 +1,116 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, PoisonError, RwLock};
+
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Values masked in every line, the longest first.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+const MASK: &str = "***";
+
+/// Shorter secrets are not masked on their own, they would mask every word that contains them.
+const MIN_SECRET_LEN: usize = 4;
+
+/// Sends the log to stderr, used while stdout carries a backup stream.
+pub fn use_stderr() {
+    STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Masks `secret` (also percent encoded) in every following line, for the passwords and keys of the config.
+/// Secrets shorter than `MIN_SECRET_LEN` are only masked in the user info of URIs.
+pub fn add_secret(secret: &str) {
+    let secret = secret.trim();
+    if secret.chars().count() < MIN_SECRET_LEN {
+        return;
+    }
+
+    let mut secrets = SECRETS.write().unwrap_or_else(PoisonError::into_inner);
+
+    for value in [secret.to_string(), crate::secrets::percent_encode(secret)] {
+        if !secrets.contains(&value) {
+            secrets.push(value);
+        }
+    }
+
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+}
+
+/// `content` with the user info of URIs (`mongodb://***@host`) and the values of `add_secret` masked.
+pub fn redact(content: &str) -> String {
+    let mut res = redact_userinfo(content);
+
+    for secret in SECRETS.read().unwrap_or_else(PoisonError::into_inner).iter() {
+        if res.contains(secret.as_str()) {
+            res = res.replace(secret.as_str(), MASK);
+        }
+    }
+
+    res
+}
+
+fn redact_userinfo(content: &str) -> String {
+    let mut res = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(index) = rest.find("://") {
+        let (before, after) = rest.split_at(index + 3);
+        res.push_str(before);
+
+        let authority_end = after.find(|c: char| matches!(c, '/' | '?' | '#' | '"' | '\'' | '<' | '>' | '`') || c.is_whitespace()).unwrap_or(after.len());
+
+        rest = match after[..authority_end].rfind('@') {
+            Some(at) => {
+                res.push_str(MASK);
+                &after[at..]
+            }
+            None => after,
+        };
+    }
+
+    res.push_str(rest);
+    res
+}
+
+fn print_line(line: String) {
+    let line = redact(&line);
+
+    match STDERR.load(Ordering::Relaxed) {
+        true => eprintln!("{line}"),
+        false => println!("{line}"),
+    }
+}
+
 pub fn info(content: &str) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::blue("{Info}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::blue("{Info}")));
 }
 
 pub fn warn(content: &str) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::yellow("{Warn}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::yellow("{Warn}")));
 }
 
 pub fn error(content: &str) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::red("{Error}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::red("{Error}")));
 }
 
 pub fn debug(content: &str) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::green("{Debug}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::green("{Debug}")));
 }
 
 
 
 pub fn info_string(content: String) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::blue("{Info}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::blue("{Info}")));
 }
 
 pub fn warn_string(content: String) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::yellow("{Warn}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::yellow("{Warn}")));
 }
 
 pub fn error_string(content: String) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::red("{Error}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::red("{Error}")));
 }
 
 pub fn debug_string(content: String) {
-    println!("[{}] {} {content}", crate::exts::get_date(), colors::green("{Debug}"));
+    print_line(format!("[{}] {} {content}", crate::exts::get_date(), colors::green("{Debug}")));
 }
 
 
@@ -50,6 +131,36 @@ pub mod colors {
     }
 
     fn parse_code(code: u8) -> String {
-        return format!("\u{001b}[{}m", code);
+        format!("\u{001b}[{}m", code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn userinfo_is_masked() {
+        assert_eq!(redact_userinfo("mongodb://u:p@h:27017/db"), "mongodb://***@h:27017/db");
+        assert_eq!(redact_userinfo("mongodb+srv://user@cluster.net?tls=true"), "mongodb+srv://***@cluster.net?tls=true");
+        assert_eq!(redact_userinfo("\"mongodb://a:b@c\" and 'mongodb://d:e@f'"), "\"mongodb://***@c\" and 'mongodb://***@f'");
+        assert_eq!(redact_userinfo("mongodb://u:p@ss@h"), "mongodb://***@h");
+        assert_eq!(redact_userinfo("mongodb://h/db?mail=a@b"), "mongodb://h/db?mail=a@b");
+        assert_eq!(redact_userinfo("no uri, a@b"), "no uri, a@b");
+    }
+
+    #[test]
+    fn secrets_are_masked() {
+        add_secret(" logger-test s3cr/t ");
+
+        assert_eq!(redact("key logger-test s3cr/t used"), "key *** used");
+        assert_eq!(redact("encoded logger-test%20s3cr%2Ft"), "encoded ***");
+    }
+
+    #[test]
+    fn short_secrets_are_ignored() {
+        add_secret("ab");
+
+        assert_eq!(redact("about mongodb://u:ab@h"), "about mongodb://***@h");
     }
 }
\ No newline at end of file
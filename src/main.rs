@@ -1,14 +1,3 @@
-mod backuper;
-mod logger;
-mod exts;
-
-#[cfg(not(target_os = "windows"))]
-const DIRECTORY: &'static str = "/MongoBackups";
-
-#[cfg(target_os = "windows")]
-const DIRECTORY: &'static str = "C:\\MongoBackups";
-
-
 #[cfg(not(target_os = "windows"))]
 #[path = "core/linux.rs"]
 mod core;
@@ -17,6 +6,9 @@ mod core;
 #[path = "core/windows.rs"]
 mod core;
 
+#[path = "core/cli.rs"]
+mod cli;
+
 #[cfg(target_os = "windows")]
 #[macro_use]
 extern crate windows_service;
@@ -24,4 +16,4 @@ extern crate windows_service;
 
 fn main() {
     core::main();
-}
\ No newline at end of file
+}
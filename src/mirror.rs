@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+
+use crate::job::Destination;
+use crate::report::{CopyReport, MANIFEST_FILE};
+use crate::signing::SIGNATURE_FILE;
+use crate::sink::ObjectWriter;
+use crate::{Error, Result};
+
+type Failures = Arc<Mutex<Vec<Option<String>>>>;
+
+/// Writes the objects of one run to every destination. A destination that fails is
+/// skipped for the rest of the run, the run itself only fails when all of them did.
+pub(crate) struct Mirror<'a> {
+    destinations: &'a [Destination],
+    run: String,
+    failures: Failures,
+}
+
+impl<'a> Mirror<'a> {
+    pub fn new(destinations: &'a [Destination], run: &str) -> Self {
+        Mirror {
+            destinations,
+            run: run.to_string(),
+            failures: Arc::new(Mutex::new(vec![None; destinations.len()])),
+        }
+    }
+
+    /// Removes a run with the same name left by an earlier backup.
+    pub async fn prepare(&self) -> Result<()> {
+        for (index, destination) in self.destinations.iter().enumerate() {
+            let result = match destination.sink.list_runs().await {
+                Ok(runs) if runs.contains(&self.run) => destination.sink.delete_run(&self.run).await,
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                self.fail(index, &err);
+            }
+        }
+
+        self.check_alive()
+    }
+
+    pub async fn create_object(&self, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        let mut writers = Vec::new();
+
+        for index in self.alive() {
+            match self.destinations[index].sink.create_object(&self.run, path).await {
+                Ok(writer) => writers.push((index, writer)),
+                Err(err) => self.fail(index, &err),
+            }
+        }
+
+        self.check_alive()?;
+
+        Ok(Box::new(MirrorWriter {
+            writers,
+            names: self.destinations.iter().map(|destination| destination.name.clone()).collect(),
+            failures: self.failures.clone(),
+        }))
+    }
+
+    /// Links `path` of `from_run` into the run on every remaining destination, `false` when
+    /// one of them could not. The object has to be written then, replacing the links.
+    pub async fn link_object(&self, from_run: &str, path: &str) -> bool {
+        for index in self.alive() {
+            let destination = &self.destinations[index];
+
+            match destination.sink.link_object(from_run, &self.run, path).await {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(err) => {
+                    crate::logger::debug_string(format!("Failed to link \"{path}\" in \"{}\" > {err}", destination.name));
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Writes the manifest (and its signature) to the remaining destinations, finalizes
+    /// the run there and removes the partial copies from the destinations that failed.
+    pub async fn finish(&self, manifest: &[u8], signature: Option<&[u8]>) -> Result<()> {
+        for index in self.alive() {
+            let sink = self.destinations[index].sink.as_ref();
+
+            let result = match crate::sink::write_object(sink, &self.run, MANIFEST_FILE, manifest).await {
+                Ok(_) => match signature {
+                    Some(signature) => crate::sink::write_object(sink, &self.run, SIGNATURE_FILE, signature).await,
+                    None => Ok(()),
+                },
+                Err(err) => Err(err),
+            };
+
+            let result = match result {
+                Ok(_) => sink.finalize(&self.run).await,
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                self.fail(index, &err);
+            }
+        }
+
+        for (index, destination) in self.destinations.iter().enumerate() {
+            if !self.alive().contains(&index) {
+                if let Err(err) = destination.sink.delete_run(&self.run).await {
+                    crate::logger::debug_string(format!("Failed to clean up \"{}\" in \"{}\" > {err}", self.run, destination.name));
+                }
+            }
+        }
+
+        self.check_alive()
+    }
+
+    pub fn copies(&self) -> Vec<CopyReport> {
+        let failures = self.failures.lock().map(|failures| failures.clone()).unwrap_or_default();
+
+        self.destinations.iter().enumerate().map(|(index, destination)| {
+            let error = failures.get(index).cloned().flatten();
+
+            CopyReport {
+                destination: destination.name.clone(),
+                location: destination.sink.describe(),
+                ok: error.is_none(),
+                error,
+            }
+        }).collect()
+    }
+
+    fn alive(&self) -> Vec<usize> {
+        match self.failures.lock() {
+            Ok(failures) => failures.iter().enumerate().filter(|(_, error)| error.is_none()).map(|(index, _)| index).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn check_alive(&self) -> Result<()> {
+        match self.alive().is_empty() {
+            true => Err(Error::Storage(format!("Run \"{}\" failed on every destination", self.run))),
+            false => Ok(()),
+        }
+    }
+
+    fn fail(&self, index: usize, err: &Error) {
+        record_failure(&self.failures, &self.destinations[index].name, index, err);
+    }
+}
+
+fn record_failure(failures: &Failures, name: &str, index: usize, err: &Error) {
+    crate::logger::warn_string(format!("Destination \"{name}\" failed > {err}"));
+
+    if let Ok(mut failures) = failures.lock() {
+        if failures[index].is_none() {
+            failures[index] = Some(err.to_string());
+        }
+    }
+}
+
+struct MirrorWriter {
+    writers: Vec<(usize, Box<dyn ObjectWriter>)>,
+    names: Vec<String>,
+    failures: Failures,
+}
+
+impl MirrorWriter {
+    fn is_failed(&self, index: usize) -> bool {
+        self.failures.lock().map(|failures| failures[index].is_some()).unwrap_or(true)
+    }
+}
+
+#[async_trait]
+impl ObjectWriter for MirrorWriter {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        let mut alive = Vec::new();
+
+        for (index, mut writer) in std::mem::take(&mut self.writers) {
+            if self.is_failed(index) {
+                continue;
+            }
+
+            match writer.write(buf).await {
+                Ok(_) => alive.push((index, writer)),
+                Err(err) => record_failure(&self.failures, &self.names[index], index, &err),
+            }
+        }
+
+        self.writers = alive;
+
+        match self.writers.is_empty() {
+            true => Err(Error::Storage("Every destination failed".to_string())),
+            false => Ok(()),
+        }
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        let mut finished = 0;
+
+        for (index, writer) in std::mem::take(&mut self.writers) {
+            if self.is_failed(index) {
+                continue;
+            }
+
+            match writer.finish().await {
+                Ok(_) => finished += 1,
+                Err(err) => record_failure(&self.failures, &self.names[index], index, &err),
+            }
+        }
+
+        match finished {
+            0 => Err(Error::Storage("Every destination failed".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+
+use crate::crypto::{Encryptor, Key};
+use crate::job::{Compression, Format};
+use crate::report::FileReport;
+use crate::sink::ObjectWriter;
+use crate::Result;
+
+const FLUSH_SIZE: usize = 1024 * 1024;
+
+/// Object of a run that is being written. Bytes are written synchronously into a
+/// buffer (optionally through the compressor) and pushed to the sink by `flush`,
+/// encrypted when a key is given.
+pub(crate) struct OutputFile {
+    pub path: String,
+    pub documents: u64,
+    gzip: Option<GzEncoder<Vec<u8>>>,
+    pending: Vec<u8>,
+    encryptor: Option<Encryptor>,
+    hasher: Sha256,
+    bytes: u64,
+    writer: Box<dyn ObjectWriter>,
+}
+
+impl OutputFile {
+    pub fn new(path: String, writer: Box<dyn ObjectWriter>, compression: Compression, key: Option<&Key>) -> Self {
+        let gzip = match compression {
+            Compression::None => None,
+            Compression::Gzip => Some(GzEncoder::new(Vec::new(), flate2::Compression::default())),
+        };
+
+        OutputFile {
+            path,
+            documents: 0,
+            gzip,
+            pending: Vec::new(),
+            encryptor: key.map(Encryptor::new),
+            hasher: Sha256::new(),
+            bytes: 0,
+            writer,
+        }
+    }
+
+    /// Pushes buffered bytes to the sink once enough of them are collected.
+    pub async fn flush(&mut self) -> Result<()> {
+        let buffered = match &self.gzip {
+            Some(encoder) => encoder.get_ref().len(),
+            None => self.pending.len(),
+        };
+
+        if buffered >= FLUSH_SIZE {
+            self.push().await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn finish(mut self, database: Option<String>, collection: Option<String>) -> Result<FileReport> {
+        if let Some(encoder) = self.gzip.take() {
+            self.pending = encoder.finish()?;
+        }
+        self.push().await?;
+
+        if let Some(encryptor) = self.encryptor.take() {
+            let data = encryptor.finish()?;
+            self.write_out(data).await?;
+        }
+
+        self.writer.finish().await?;
+
+        Ok(FileReport {
+            path: self.path,
+            database,
+            collection,
+            documents: self.documents,
+            bytes: self.bytes,
+            sha256: hex::encode(self.hasher.finalize()),
+            format: Format::Bson,
+            columns: Vec::new(),
+            fingerprint: None,
+        })
+    }
+
+    async fn push(&mut self) -> Result<()> {
+        let data = match &mut self.gzip {
+            Some(encoder) => std::mem::take(encoder.get_mut()),
+            None => std::mem::take(&mut self.pending),
+        };
+
+        let data = match &mut self.encryptor {
+            Some(encryptor) => encryptor.update(&data)?,
+            None => data,
+        };
+
+        self.write_out(data).await
+    }
+
+    async fn write_out(&mut self, data: Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.hasher.update(&data);
+        self.bytes += data.len() as u64;
+        self.writer.write(&data).await
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.gzip {
+            Some(encoder) => encoder.write(buf),
+            None => self.pending.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! Where the config and the backups are. Every path can be set on the command line or with an
+//! environment variable, the defaults are `DIRECTORY` when it exists and the XDG directories otherwise.
+
+use std::{path::{Path, PathBuf}, sync::OnceLock};
+
+use crate::config::CONFIG_FILES;
+
+/// Directory of the config and the backups, like `--dir`.
+pub const DIR_ENV: &str = "MONGO_BACKUPER_DIR";
+/// Config file, like `--config`.
+pub const CONFIG_ENV: &str = "MONGO_BACKUPER_CONFIG";
+/// Directory the runs of every connection go to (`<output>/<name>`), like `--output`.
+pub const OUTPUT_ENV: &str = "MONGO_BACKUPER_OUTPUT";
+
+const APP: &str = "mongo_backuper";
+
+static DIRECTORY: OnceLock<Option<PathBuf>> = OnceLock::new();
+static CONFIG: OnceLock<PathBuf> = OnceLock::new();
+static OUTPUT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory of the config and the backups, only before any path is used.
+pub fn set_directory(path: impl Into<PathBuf>) -> bool {
+    DIRECTORY.set(Some(path.into())).is_ok()
+}
+
+/// Sets the config file, only before `config_path` is used.
+pub fn set_config_path(path: impl Into<PathBuf>) -> bool {
+    CONFIG.set(path.into()).is_ok()
+}
+
+/// Sets the directory of the runs, only before `output` is used.
+pub fn set_output(path: impl Into<PathBuf>) -> bool {
+    OUTPUT.set(path.into()).is_ok()
+}
+
+/// Directory given with `set_directory` or `MONGO_BACKUPER_DIR`, otherwise `DIRECTORY` when it exists.
+fn directory() -> Option<&'static Path> {
+    DIRECTORY.get_or_init(|| match env_path(DIR_ENV) {
+        Some(res) => Some(res),
+        None => Some(PathBuf::from(crate::DIRECTORY)).filter(|legacy| legacy.exists() || cfg!(target_os = "windows")),
+    }).as_deref()
+}
+
+/// Config file of the connections: `set_config_path`, `MONGO_BACKUPER_CONFIG`, `config.js` (or
+/// another of `CONFIG_FILES`) in the directory, or in `$XDG_CONFIG_HOME/mongo_backuper`.
+pub fn config_path() -> &'static Path {
+    CONFIG.get_or_init(|| {
+        if let Some(res) = env_path(CONFIG_ENV) {
+            return res;
+        }
+
+        match directory() {
+            Some(directory) => find_config(directory),
+            None => find_config(&xdg("XDG_CONFIG_HOME", ".config")),
+        }
+    })
+}
+
+/// Directory with the runs of the connections without `storage` or `output` of their own:
+/// `set_output`, `MONGO_BACKUPER_OUTPUT`, `Backups` in the directory, or `$XDG_DATA_HOME/mongo_backuper/Backups`.
+pub fn output() -> &'static Path {
+    OUTPUT.get_or_init(|| {
+        if let Some(res) = env_path(OUTPUT_ENV) {
+            return res;
+        }
+
+        match directory() {
+            Some(directory) => directory.join("Backups"),
+            None => xdg("XDG_DATA_HOME", ".local/share").join("Backups"),
+        }
+    })
+}
+
+/// First of `CONFIG_FILES` that exists in `directory`, `config.js` when there is none.
+fn find_config(directory: &Path) -> PathBuf {
+    CONFIG_FILES.iter()
+        .map(|name| directory.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| directory.join(CONFIG_FILES[0]))
+}
+
+fn env_path(variable: &str) -> Option<PathBuf> {
+    std::env::var_os(variable).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+/// `mongo_backuper` in the XDG base directory `variable`, `~/<fallback>` when it is not set.
+/// Without a home directory everything stays in `DIRECTORY`.
+fn xdg(variable: &str, fallback: &str) -> PathBuf {
+    let base = match env_path(variable).filter(|path| path.is_absolute()) {
+        Some(res) => res,
+        None => match env_path("HOME") {
+            Some(home) => home.join(fallback),
+            None => return PathBuf::from(crate::DIRECTORY),
+        },
+    };
+
+    base.join(APP)
+}
+
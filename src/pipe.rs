@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::job::Compression;
+use crate::sink::{ObjectReader, ObjectWriter};
+use crate::Result;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Writes an archive stream to a pipe (stdout by default) instead of a sink.
+pub struct PipeWriter<W> {
+    writer: W,
+}
+
+impl PipeWriter<tokio::io::Stdout> {
+    pub fn stdout() -> Self {
+        PipeWriter { writer: tokio::io::stdout() }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> PipeWriter<W> {
+    pub fn new(writer: W) -> Self {
+        PipeWriter { writer }
+    }
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Unpin + Send> ObjectWriter for PipeWriter<W> {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.writer.write_all(buf).await?;
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Reads an archive stream from a pipe (stdin by default).
+pub struct PipeReader<R> {
+    reader: R,
+    /// Data read ahead by `compression`.
+    peeked: Vec<u8>,
+}
+
+impl PipeReader<tokio::io::Stdin> {
+    pub fn stdin() -> Self {
+        PipeReader { reader: tokio::io::stdin(), peeked: Vec::new() }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> PipeReader<R> {
+    pub fn new(reader: R) -> Self {
+        PipeReader { reader, peeked: Vec::new() }
+    }
+
+    /// Compression of the stream, told apart by the gzip magic bytes.
+    pub async fn compression(&mut self) -> Result<Compression> {
+        while self.peeked.len() < GZIP_MAGIC.len() {
+            let mut chunk = vec![0; GZIP_MAGIC.len() - self.peeked.len()];
+            let read = self.reader.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            self.peeked.extend_from_slice(&chunk[..read]);
+        }
+
+        match self.peeked.starts_with(&GZIP_MAGIC) {
+            true => Ok(Compression::Gzip),
+            false => Ok(Compression::None),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> ObjectReader for PipeReader<R> {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.peeked.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.peeked)));
+        }
+
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let read = self.reader.read(&mut chunk).await?;
+
+        match read {
+            0 => Ok(None),
+            read => {
+                chunk.truncate(read);
+                Ok(Some(chunk))
+            }
+        }
+    }
+}
+
@@ -0,0 +1,172 @@
+use flate2::write::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+use crate::crypto::{Decryptor, Key};
+use crate::job::{Compression, Format};
+use crate::sink::{BackupSink, ObjectReader};
+use crate::{Error, Result};
+
+/// Reads an object of a run and undoes the encryption and compression applied by the backup.
+pub struct ObjectStream {
+    reader: Box<dyn ObjectReader>,
+    decryptor: Option<Decryptor>,
+    gunzip: Option<GzDecoder<Vec<u8>>>,
+    hasher: Sha256,
+    bytes: u64,
+    done: bool,
+}
+
+impl ObjectStream {
+    pub async fn open(sink: &dyn BackupSink, run: &str, path: &str, compression: Compression, key: Option<&Key>) -> Result<Self> {
+        let reader = sink.open_object(run, path).await?;
+        Ok(ObjectStream::new(reader, compression, key))
+    }
+
+    /// Reads from any `reader`, like a pipe that carries an archive stream.
+    pub fn new(reader: Box<dyn ObjectReader>, compression: Compression, key: Option<&Key>) -> Self {
+        let gunzip = match compression {
+            Compression::None => None,
+            Compression::Gzip => Some(GzDecoder::new(Vec::new())),
+        };
+
+        ObjectStream { reader, decryptor: key.map(Decryptor::new), gunzip, hasher: Sha256::new(), bytes: 0, done: false }
+    }
+
+    /// Returns the next chunk of plain data, `None` at the end of the object.
+    pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+
+            let (chunk, last) = match self.reader.read_chunk().await? {
+                Some(chunk) => {
+                    self.hasher.update(&chunk);
+                    self.bytes += chunk.len() as u64;
+
+                    match &mut self.decryptor {
+                        Some(decryptor) => (decryptor.update(&chunk)?, false),
+                        None => (chunk, false),
+                    }
+                }
+                None => match self.decryptor.take() {
+                    Some(decryptor) => (decryptor.finish()?, true),
+                    None => (Vec::new(), true),
+                },
+            };
+
+            if last {
+                self.done = true;
+
+                let data = match self.gunzip.take() {
+                    Some(mut decoder) => {
+                        decoder.write_all(&chunk)?;
+                        decoder.finish()?
+                    }
+                    None => chunk,
+                };
+
+                return match data.is_empty() {
+                    true => Ok(None),
+                    false => Ok(Some(data)),
+                };
+            }
+
+            let data = match &mut self.gunzip {
+                Some(decoder) => {
+                    decoder.write_all(&chunk)?;
+                    std::mem::take(decoder.get_mut())
+                }
+                None => chunk,
+            };
+
+            if !data.is_empty() {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    /// Size and sha256 of the stored bytes read so far.
+    pub fn checksum(&self) -> (u64, String) {
+        (self.bytes, hex::encode(self.hasher.clone().finalize()))
+    }
+}
+
+/// Splits a stream of concatenated BSON documents, or of Extended JSON lines that are
+/// returned as BSON too.
+#[derive(Default)]
+pub struct DocumentSplitter {
+    buffer: Vec<u8>,
+    format: Format,
+}
+
+impl DocumentSplitter {
+    pub fn new(format: Format) -> Self {
+        DocumentSplitter { buffer: Vec::new(), format }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    pub fn next_document(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.format.is_bson() {
+            return self.next_line();
+        }
+
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
+
+        let length = i32::from_le_bytes([self.buffer[0], self.buffer[1], self.buffer[2], self.buffer[3]]);
+        if length < 5 {
+            return Err(Error::Corrupt(format!("Invalid document length {length}")));
+        }
+
+        let length = length as usize;
+        if self.buffer.len() < length {
+            return Ok(None);
+        }
+
+        let rest = self.buffer.split_off(length);
+        Ok(Some(std::mem::replace(&mut self.buffer, rest)))
+    }
+
+    fn next_line(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            let end = match self.buffer.iter().position(|byte| *byte == b'\n') {
+                Some(res) => res,
+                None => return Ok(None),
+            };
+
+            let rest = self.buffer.split_off(end + 1);
+            let line = std::mem::replace(&mut self.buffer, rest);
+
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+
+            let value: serde_json::Value = serde_json::from_slice(&line)?;
+            let doc = match bson::Bson::try_from(value) {
+                Ok(bson::Bson::Document(doc)) => doc,
+                Ok(_) => return Err(Error::Corrupt("Extended JSON line is not a document".to_string())),
+                Err(err) => return Err(Error::Corrupt(format!("Invalid Extended JSON: {err}"))),
+            };
+
+            return bson::to_vec(&doc).map(Some).map_err(|err| Error::Corrupt(err.to_string()));
+        }
+    }
+
+    /// Checks that nothing but whole documents was pushed.
+    pub fn finish(&self) -> Result<()> {
+        if !self.format.is_bson() && self.buffer.trim_ascii().is_empty() {
+            return Ok(());
+        }
+
+        match self.buffer.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Corrupt(format!("{} trailing bytes after the last document", self.buffer.len()))),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Write;
+
+use crate::crypto::{Encryption, Key, Secret};
+use crate::job::Compression;
+use crate::output::OutputFile;
+use crate::reader::ObjectStream;
+use crate::report::{load_manifest, BackupReport, EncryptionReport, MANIFEST_FILE};
+use crate::signing::{SigningKey, SIGNATURE_FILE};
+use crate::sink::BackupSink;
+use crate::{Error, Result};
+
+/// Suffix of the run that receives the re-encrypted copy of a run.
+pub const STAGING: &str = ".rekey";
+
+#[derive(Clone, Debug, Default)]
+pub struct RekeyReport {
+    /// Runs that are encrypted with the new key now.
+    pub rekeyed: Vec<String>,
+    /// Runs that already used the new key or are not encrypted.
+    pub skipped: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Re-encrypts every run of `sink` that `old` can read with `new`. Runs are replaced
+/// one by one and an interrupted rekey is completed by the next call. The changed
+/// manifests are signed again with `signer`.
+pub async fn rekey(sink: &dyn BackupSink, old: &Secret, new: &Encryption, signer: Option<&SigningKey>) -> Result<RekeyReport> {
+    let mut report = RekeyReport::default();
+
+    let mut runs: Vec<String> = Vec::new();
+
+    // A staging run without its run is a rekey interrupted during the swap
+    for run in sink.list_runs().await? {
+        let run = run.strip_suffix(STAGING).unwrap_or(&run).to_string();
+        if !runs.contains(&run) {
+            runs.push(run);
+        }
+    }
+
+    for run in runs {
+        match rekey_run(sink, &run, old, new, signer).await {
+            Ok(true) => report.rekeyed.push(run),
+            Ok(false) => report.skipped.push(run),
+            Err(err) => {
+                crate::logger::warn_string(format!("Failed to rekey \"{run}\" > {err}"));
+                report.failed.push((run, err.to_string()));
+            }
+        }
+    }
+
+    // Replaced runs and staging copies can leave shared data behind
+    if !report.rekeyed.is_empty() || !report.failed.is_empty() {
+        sink.after_prune().await?;
+    }
+
+    Ok(report)
+}
+
+/// Re-encrypts one run, returns `false` when there was nothing to do.
+///
+/// The copy is written to `<run>.rekey` and only replaces the run once its manifest is
+/// written. A staging run with a manifest is complete and is swapped in on resume,
+/// one without is a leftover and is written again.
+pub async fn rekey_run(sink: &dyn BackupSink, run: &str, old: &Secret, new: &Encryption, signer: Option<&SigningKey>) -> Result<bool> {
+    let staging = format!("{run}{STAGING}");
+
+    if sink.list_runs().await?.contains(&staging) {
+        match load_manifest(sink, &staging).await {
+            Ok(_) => {
+                crate::logger::debug_string(format!("Completing the interrupted rekey of \"{run}\""));
+                swap(sink, &staging, run).await?;
+                return Ok(true);
+            }
+            Err(_) => sink.delete_run(&staging).await?,
+        }
+    }
+
+    let mut manifest = load_manifest(sink, run).await?;
+
+    let encryption = match &manifest.encryption {
+        Some(encryption) if !new.matches(encryption) => encryption,
+        _ => return Ok(false),
+    };
+
+    let old_key = old.unlock(encryption)?;
+    let (new_key, new_encryption) = new.run_key()?;
+
+    crate::logger::debug_string(format!("Rekeying \"{run}\" from {} to {}", old_key.id(), new_key.id()));
+
+    if signer.is_none() && sink.list_objects(run).await?.iter().any(|object| object == SIGNATURE_FILE) {
+        crate::logger::warn_string(format!("\"{run}\" is signed, its new manifest stays unsigned without a signing key"));
+    }
+
+    if let Err(err) = write_staging(sink, &mut manifest, &staging, &old_key, &new_key, new_encryption, signer).await {
+        sink.delete_run(&staging).await.unwrap_or_default();
+        return Err(err);
+    }
+
+    swap(sink, &staging, run).await?;
+    Ok(true)
+}
+
+async fn write_staging(sink: &dyn BackupSink, manifest: &mut BackupReport, staging: &str, old_key: &Key, new_key: &Key, encryption: EncryptionReport, signer: Option<&SigningKey>) -> Result<()> {
+    let mut files = Vec::new();
+
+    for file in &manifest.files {
+        // Compression is kept, only the encryption layer is replaced
+        let mut stream = ObjectStream::open(sink, &manifest.run, &file.path, Compression::None, Some(old_key)).await?;
+        let mut output = OutputFile::new(file.path.clone(), sink.create_object(staging, &file.path).await?, Compression::None, Some(new_key));
+
+        while let Some(chunk) = stream.next_chunk().await? {
+            output.write_all(&chunk)?;
+            output.flush().await?;
+        }
+
+        let (bytes, sha256) = stream.checksum();
+        if bytes != file.bytes || sha256 != file.sha256 {
+            return Err(Error::Corrupt(format!("{} does not match its checksum", file.path)));
+        }
+
+        output.documents = file.documents;
+        let mut rekeyed = output.finish(file.database.clone(), file.collection.clone()).await?;
+        rekeyed.format = file.format;
+        rekeyed.fingerprint = file.fingerprint.clone();
+        files.push(rekeyed);
+    }
+
+    manifest.files = files;
+    manifest.encryption = Some(encryption);
+
+    let data = serde_json::to_vec_pretty(manifest)?;
+
+    // The signature goes first: a staging run is complete once its manifest exists
+    if let Some(signer) = signer {
+        crate::sink::write_object(sink, staging, SIGNATURE_FILE, &crate::signing::sign(signer, &data)?).await?;
+    }
+
+    crate::sink::write_object(sink, staging, MANIFEST_FILE, &data).await?;
+    sink.finalize(staging).await
+}
+
+/// Replaces `run` with the complete copy in `staging`.
+async fn swap(sink: &dyn BackupSink, staging: &str, run: &str) -> Result<()> {
+    sink.delete_run(run).await?;
+    crate::sink::copy_run(sink, staging, sink, run).await?;
+    sink.delete_run(staging).await
+}
@@ -0,0 +1,95 @@
+//! Noticing changes of the config while the daemon runs: the modification time of the file is
+//! checked every few seconds, and on Unix `SIGHUP` asks for a reload right away.
+
+use std::{path::{Path, PathBuf}, time::SystemTime};
+use tokio::time::{interval, Duration, Interval, MissedTickBehavior};
+
+/// How often the modification time of the config is checked.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct ConfigWatcher {
+    path: PathBuf,
+    /// Modification time and size at the last check, `None` while the file can not be read.
+    stamp: Option<(SystemTime, u64)>,
+    checks: Interval,
+    hangup: Hangup,
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+
+#[cfg(not(unix))]
+type Hangup = ();
+
+impl ConfigWatcher {
+    /// Watches `path` from its current state on, changes before this are not reported.
+    /// Needs a tokio runtime.
+    pub fn new(path: &Path) -> Self {
+        let mut checks = interval(CHECK_INTERVAL);
+        checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        ConfigWatcher {
+            path: path.to_path_buf(),
+            stamp: stamp(path),
+            checks,
+            hangup: listen_hangup(),
+        }
+    }
+
+    /// Waits until the config has been written or the process got `SIGHUP`.
+    pub async fn changed(&mut self) {
+        loop {
+            tokio::select! {
+                _ = self.checks.tick() => {
+                    let stamp = stamp(&self.path);
+                    if stamp != self.stamp {
+                        self.stamp = stamp;
+                        // A file that has just gone is usually being replaced, its new version is the change
+                        if self.stamp.is_some() {
+                            return;
+                        }
+                    }
+                }
+                _ = hangup(&mut self.hangup) => {
+                    crate::logger::info("Got SIGHUP, reloading the config");
+                    self.stamp = stamp(&self.path);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn listen_hangup() -> Hangup {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(res) => Some(res),
+        Err(err) => {
+            crate::logger::warn_string(format!("SIGHUP can not reload the config: {err}"));
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn listen_hangup() -> Hangup {}
+
+#[cfg(unix)]
+async fn hangup(hangup: &mut Hangup) {
+    match hangup {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn hangup(_hangup: &mut Hangup) {
+    std::future::pending().await
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
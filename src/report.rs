@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{Key, Secret};
+use crate::export::Column;
+use crate::job::{Compression, Format};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Result of a single backup run. It is also written next to the dumped
+/// files as `manifest.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupReport {
+    pub name: String,
+    pub run: String,
+    #[serde(rename = "startedAt")]
+    pub started_at: i64,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: i64,
+    pub compression: Compression,
+    #[serde(default)]
+    pub format: Format,
+    pub archive: bool,
+    pub files: Vec<FileReport>,
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// Destinations the run was written to. The stored manifest can only tell the state before it
+    /// was written itself: a destination that fails writing the manifest, the signature or
+    /// finalizing (its run is removed) is only marked failed in the report `run_once` returns.
+    #[serde(default)]
+    pub copies: Vec<CopyReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionReport>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileReport {
+    pub path: String,
+    pub database: Option<String>,
+    pub collection: Option<String>,
+    pub documents: u64,
+    pub bytes: u64,
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Format::is_bson")]
+    pub format: Format,
+    /// Inferred schema of a Parquet export.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<Column>,
+    /// `dbHash` of the collection when it was dumped, unchanged collections are linked from the previous run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptionReport {
+    pub algorithm: String,
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    /// age recipients the key of the run is encrypted to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    /// Key of the run encrypted to `recipients` (base64 of an age file).
+    #[serde(default, rename = "wrappedKey", skip_serializing_if = "Option::is_none")]
+    pub wrapped_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CopyReport {
+    pub destination: String,
+    pub location: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BackupReport {
+    /// Files that hold every document once: the first file of every collection, which is the
+    /// dump when a collection is written in several formats. The SQLite snapshot is left out.
+    pub fn document_files(&self) -> impl Iterator<Item = &FileReport> {
+        let mut seen = Vec::new();
+
+        self.files.iter().filter(|file| file.format != Format::Sqlite).filter(move |file| {
+            let namespace = (&file.database, &file.collection);
+
+            match file.database.is_none() || !seen.contains(&namespace) {
+                true => {
+                    seen.push(namespace);
+                    true
+                }
+                false => false,
+            }
+        })
+    }
+
+    pub fn documents(&self) -> u64 {
+        self.document_files().map(|file| file.documents).sum()
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.bytes).sum()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Key to read the files of the run with, fails when the run is encrypted and `secret` is missing or can not read it.
+    pub fn key(&self, secret: Option<&Secret>) -> crate::Result<Option<Key>> {
+        let encryption = match &self.encryption {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+
+        match secret {
+            Some(secret) => secret.unlock(encryption).map(Some),
+            None => Err(crate::Error::Config(format!("Run \"{}\" is encrypted, a key is needed to read it", self.run))),
+        }
+    }
+}
+
+pub async fn load_manifest(sink: &dyn crate::sink::BackupSink, run: &str) -> crate::Result<BackupReport> {
+    let data = crate::sink::read_object(sink, run, MANIFEST_FILE).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
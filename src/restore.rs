@@ -0,0 +1,172 @@
+use bson::RawDocumentBuf;
+use mongodb::{Client, Collection};
+use tokio::io::AsyncRead;
+
+use crate::archive::{ArchiveParser, Entry};
+use crate::crypto::{Key, Secret};
+use crate::job::Format;
+use crate::pipe::PipeReader;
+use crate::reader::{DocumentSplitter, ObjectStream};
+use crate::report::{load_manifest, BackupReport, FileReport};
+use crate::sink::BackupSink;
+use crate::{Error, Result};
+
+const BATCH_SIZE: usize = 1000;
+
+/// Loads a run from `sink` back into the MongoDB server at `url`.
+/// With `drop` every restored collection is dropped before inserting, `secret` is needed for encrypted runs.
+/// Returns the number of restored documents.
+pub async fn restore(sink: &dyn BackupSink, run: &str, url: &str, drop: bool, secret: Option<&Secret>) -> Result<u64> {
+    let manifest = load_manifest(sink, run).await?;
+    let key = manifest.key(secret)?;
+    let client = Client::with_uri_str(url).await?;
+
+    crate::logger::info_string(format!("Restoring \"{run}\" of \"{}\"", &manifest.name));
+
+    if manifest.document_files().next().is_none() && !manifest.files.is_empty() {
+        return Err(Error::Config(format!("Run \"{run}\" only has a SQLite snapshot, which can not be restored")));
+    }
+
+    let mut documents = 0;
+
+    for file in manifest.document_files() {
+        documents += match manifest.archive {
+            true => {
+                let stream = ObjectStream::open(sink, &manifest.run, &file.path, manifest.compression, key.as_ref()).await?;
+                restore_archive(stream, &client, drop).await?
+            }
+            false => restore_file(sink, &manifest, file, &client, drop, key.as_ref()).await?,
+        };
+    }
+
+    crate::logger::info_string(format!("Restored {documents} documents of \"{}\"", &manifest.name));
+
+    Ok(documents)
+}
+
+async fn restore_file(sink: &dyn BackupSink, manifest: &BackupReport, file: &FileReport, client: &Client, drop: bool, key: Option<&Key>) -> Result<u64> {
+    let (database, collection) = match (&file.database, &file.collection) {
+        (Some(database), Some(collection)) => (database, collection),
+        _ => return Err(Error::Corrupt(format!("File \"{}\" has no namespace", file.path))),
+    };
+
+    if file.format == Format::Parquet {
+        return Err(Error::Config(format!("\"{}\" is a Parquet export and can not be restored", file.path)));
+    }
+
+    let mut target = Target::open(client, database, collection, drop).await?;
+    let mut stream = ObjectStream::open(sink, &manifest.run, &file.path, manifest.compression, key).await?;
+    let mut splitter = DocumentSplitter::new(file.format);
+
+    while let Some(chunk) = stream.next_chunk().await? {
+        splitter.push(&chunk);
+
+        while let Some(doc) = splitter.next_document()? {
+            target.insert(doc).await?;
+        }
+    }
+
+    splitter.finish()?;
+    target.finish().await
+}
+
+/// Loads an archive stream that is read from `reader` (e.g. `PipeReader::stdin()`) into the
+/// MongoDB server at `url`, gzip compressed streams are detected. Returns the number of restored documents.
+pub async fn restore_stream<R: AsyncRead + Unpin + Send + 'static>(mut reader: PipeReader<R>, url: &str, drop: bool) -> Result<u64> {
+    let compression = reader.compression().await?;
+    let client = Client::with_uri_str(url).await?;
+
+    crate::logger::info("Restoring an archive stream");
+
+    let documents = restore_archive(ObjectStream::new(Box::new(reader), compression, None), &client, drop).await?;
+
+    crate::logger::info_string(format!("Restored {documents} documents of the archive stream"));
+
+    Ok(documents)
+}
+
+async fn restore_archive(mut stream: ObjectStream, client: &Client, drop: bool) -> Result<u64> {
+    let mut parser = ArchiveParser::default();
+    let mut target: Option<Target> = None;
+    let mut documents = 0;
+    let mut ended = false;
+
+    while let Some(chunk) = stream.next_chunk().await? {
+        parser.push(&chunk);
+
+        while let Some(entry) = parser.next_entry()? {
+            match entry {
+                Entry::Namespace(database, collection) => {
+                    if let Some(previous) = target.take() {
+                        documents += previous.finish().await?;
+                    }
+                    target = Some(Target::open(client, &database, &collection, drop).await?);
+                }
+                Entry::Document(doc) => match target.as_mut() {
+                    Some(target) => target.insert(doc).await?,
+                    None => return Err(Error::Corrupt("Document before namespace in archive".to_string())),
+                },
+                Entry::End => ended = true,
+            }
+        }
+    }
+
+    if let Some(previous) = target.take() {
+        documents += previous.finish().await?;
+    }
+
+    // A stream cut off by a broken pipe still parses up to the last whole document
+    if !ended {
+        return Err(Error::Corrupt(format!("Archive ends after {documents} documents without its end marker")));
+    }
+
+    Ok(documents)
+}
+
+/// Collection that receives restored documents in batches.
+struct Target {
+    collection: Collection<RawDocumentBuf>,
+    batch: Vec<RawDocumentBuf>,
+    documents: u64,
+}
+
+impl Target {
+    async fn open(client: &Client, database: &str, collection: &str, drop: bool) -> Result<Self> {
+        let collection = client.database(database).collection::<RawDocumentBuf>(collection);
+
+        if drop {
+            collection.drop(None).await?;
+        }
+
+        crate::logger::debug_string(format!("Restoring \"{database}.{}\"", collection.name()));
+
+        Ok(Target { collection, batch: Vec::new(), documents: 0 })
+    }
+
+    async fn insert(&mut self, doc: Vec<u8>) -> Result<()> {
+        let doc = RawDocumentBuf::from_bytes(doc).map_err(|err| Error::Corrupt(err.to_string()))?;
+        self.batch.push(doc);
+
+        if self.batch.len() >= BATCH_SIZE {
+            self.write_batch().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<u64> {
+        self.write_batch().await?;
+        Ok(self.documents)
+    }
+
+    async fn write_batch(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        self.documents += batch.len() as u64;
+        self.collection.insert_many(batch, None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use std::time::Duration;
+use time::{OffsetDateTime, Date, Time, Month};
+
+use crate::job::Destination;
+use crate::sink::BackupSink;
+
+/// Lifecycle of the runs of a destination. With a tier runs older than its `move_after`
+/// are moved there and deleted after the retention of the tier, otherwise they are
+/// deleted after the retention of the destination. The config makes sure that `move_after`
+/// is not longer than that retention. Returns `false` when runs could not be listed, moved or deleted.
+pub async fn sweep(destination: &Destination, name: &str, default_retention: Option<Duration>) -> bool {
+    if let Some(tier) = &destination.tier {
+        let moved = move_old_runs(destination.sink.as_ref(), tier.sink.as_ref(), &tier.name, name, tier.move_after).await;
+
+        let deleted = match tier.retention {
+            Some(retention) => delete_old_runs(tier.sink.as_ref(), name, retention).await,
+            None => true,
+        };
+
+        return moved && deleted;
+    }
+
+    match destination.retention.or(default_retention) {
+        Some(retention) => delete_old_runs(destination.sink.as_ref(), name, retention).await,
+        None => true,
+    }
+}
+
+/// Moves runs older than `move_after` from `from` to `to`, but keeps one backup in any occasions.
+/// Returns `false` when a run could not be listed or moved.
+pub async fn move_old_runs(from: &dyn BackupSink, to: &dyn BackupSink, tier_name: &str, name: &str, move_after: Duration) -> bool {
+    let mut runs = match from.list_runs().await {
+        Ok(res) => res,
+        Err(err) => {
+            crate::logger::warn_string(format!("Failed to list the runs of \"{name}\" > {err}"));
+            return false;
+        }
+    };
+
+    // Staging copies and stray directories do not count as the backup that is kept
+    runs.retain(|run| parse_run_name(run).is_some());
+
+    let runs_len = runs.len();
+    let mut remaining = runs_len;
+    let mut ok = true;
+
+    for run in runs {
+        if remaining < 2 {
+            break;
+        }
+
+        if !is_older(&run, move_after) {
+            continue;
+        }
+
+        crate::logger::debug_string(format!("Moving \"{run}\" of \"{name}\" to \"{tier_name}\""));
+
+        match move_run(from, to, &run).await {
+            Ok(_) => remaining -= 1,
+            Err(err) => {
+                crate::logger::warn_string(format!("Failed to move \"{run}\" of \"{name}\" to \"{tier_name}\" > {err}"));
+                ok = false;
+            }
+        }
+    }
+
+    let cleaned = after_prune(from, name, remaining < runs_len).await;
+    ok && cleaned
+}
+
+/// Copies every object of `run` to `to` and deletes the run from `from` once the copy is complete.
+pub async fn move_run(from: &dyn BackupSink, to: &dyn BackupSink, run: &str) -> crate::Result<()> {
+    if let Err(err) = crate::sink::copy_run(from, run, to, run).await {
+        to.delete_run(run).await.unwrap_or_default();
+        return Err(err);
+    }
+
+    from.delete_run(run).await
+}
+
+fn is_older(run: &str, age: Duration) -> bool {
+    let datetime = match parse_run_name(run) {
+        Some(res) => res,
+        None => return false,
+    };
+
+    let unix = OffsetDateTime::now_local().unwrap_or(OffsetDateTime::now_utc());
+    unix.unix_timestamp() - datetime.unix_timestamp() > age.as_secs() as i64
+}
+
+/// Removes runs older than `remove_old`, but keeps one backup in any occasions.
+/// Returns `false` when a run could not be listed or deleted.
+pub async fn delete_old_runs(sink: &dyn BackupSink, name: &str, remove_old: Duration) -> bool {
+    crate::logger::debug_string(format!("Checking and deleting old backups of \"{name}\""));
+
+    let mut runs = match sink.list_runs().await {
+        Ok(res) => res,
+        Err(err) => {
+            crate::logger::warn_string(format!("Failed to list the runs of \"{name}\" > {err}"));
+            return false;
+        }
+    };
+
+    // Staging copies and stray directories do not count as the backup that is kept
+    runs.retain(|run| parse_run_name(run).is_some());
+
+    let runs_len = runs.len();
+    let mut remaining = runs_len;
+    let mut ok = true;
+
+    for run in runs {
+        if remaining < 2 {
+            break;
+        }
+
+        if is_older(&run, remove_old) {
+            crate::logger::debug_string(format!("Removing directory \"{run}\" of \"{name}\""));
+
+            match sink.delete_run(&run).await {
+                Ok(_) => remaining -= 1,
+                Err(err) => {
+                    crate::logger::warn_string(format!("Failed to remove \"{run}\" of \"{name}\" > {err}"));
+                    ok = false;
+                }
+            }
+        }
+    }
+
+    let cleaned = after_prune(sink, name, remaining < runs_len).await;
+    ok && cleaned
+}
+
+/// Lets `sink` clean up once after runs were removed from it.
+async fn after_prune(sink: &dyn BackupSink, name: &str, removed: bool) -> bool {
+    if !removed {
+        return true;
+    }
+
+    match sink.after_prune().await {
+        Ok(_) => true,
+        Err(err) => {
+            crate::logger::warn_string(format!("Failed to clean up after removing runs of \"{name}\" > {err}"));
+            false
+        }
+    }
+}
+
+/// Parses a run directory name created by `exts::get_date_file` (`YYYY.MM.DD HH-MM`).
+pub fn parse_run_name(name: &str) -> Option<OffsetDateTime> {
+    if !name.contains('.') || !name.contains(' ') || !name.contains('-') {
+        return None;
+    }
+
+    // Copies of a run being re-encrypted are not runs of their own
+    if name.ends_with(crate::rekey::STAGING) {
+        return None;
+    }
+
+    let arr1: Vec<_> = name.split(' ').collect();
+
+    let year: i32;
+    let month: Month;
+    let day: u8;
+    let hours: u8;
+    let minutes: u8;
+
+    if let Some(first) = arr1.first() {
+        let local_arr: Vec<_> = first.trim().split('.').collect();
+
+        if let Some(min) = local_arr.first() {
+            year = min.to_string().parse::<i32>().unwrap_or_default();
+        } else {
+            return None;
+        }
+
+        if local_arr.len() > 1 {
+            let pre_mnth = local_arr[1];
+            let pre_mnth = pre_mnth.to_string().parse::<u8>().unwrap_or_default();
+            month = Month::try_from(pre_mnth).unwrap_or(Month::January);
+        } else {
+            return None;
+        }
+
+        if let Some(hrs) = local_arr.last() {
+            day = hrs.to_string().parse::<u8>().unwrap_or_default();
+        } else {
+            return None;
+        }
+    } else {
+        return None;
+    }
+
+    if let Some(last) = arr1.last() {
+        let local_arr: Vec<_> = last.trim().split('-').collect();
+
+        if let Some(hrs) = local_arr.first() {
+            hours = hrs.to_string().parse::<u8>().unwrap_or_default();
+        } else {
+            return None;
+        }
+
+        if let Some(min) = local_arr.last() {
+            minutes = min.to_string().parse::<u8>().unwrap_or_default();
+        } else {
+            return None;
+        }
+    } else {
+        return None;
+    }
+
+    let date = Date::from_calendar_date(year, month, day).ok()?;
+    let time = Time::from_hms(hours, minutes, 00).ok()?;
+
+    let mut datetime = OffsetDateTime::now_utc();
+    datetime = datetime.replace_date(date);
+    datetime = datetime.replace_time(time);
+
+    Some(datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::{write_object, Memory};
+
+    const DAY: Duration = Duration::from_secs(24 * 3600);
+
+    async fn sink_with(runs: &[&str]) -> Memory {
+        let sink = Memory::new();
+        for run in runs {
+            write_object(&sink, run, "manifest.json", b"{}").await.unwrap();
+        }
+        sink
+    }
+
+    #[test]
+    fn run_names() {
+        assert!(parse_run_name("2024.03.09 17-05").is_some());
+        assert!(parse_run_name("2024.03.09 17-05.rekey").is_none());
+        assert!(parse_run_name("2024.13.40 17-05").is_none());
+        assert!(parse_run_name("lost+found").is_none());
+    }
+
+    #[tokio::test]
+    async fn delete_old_runs_keeps_the_newest() {
+        let newest = crate::exts::get_date_file();
+        let sink = sink_with(&["2020.01.01 00-00", "2020.01.02 00-00", &newest]).await;
+
+        assert!(delete_old_runs(&sink, "test", DAY).await);
+        assert_eq!(sink.list_runs().await.unwrap(), vec![newest]);
+    }
+
+    #[tokio::test]
+    async fn delete_old_runs_ignores_other_directories() {
+        let sink = sink_with(&["2020.01.01 00-00", "2020.01.02 00-00.rekey", "notes"]).await;
+
+        assert!(delete_old_runs(&sink, "test", DAY).await);
+        assert_eq!(sink.list_runs().await.unwrap(), vec!["2020.01.01 00-00", "2020.01.02 00-00.rekey", "notes"]);
+    }
+    #[tokio::test]
+    async fn move_old_runs_keeps_the_newest() {
+        let from = sink_with(&["2020.01.01 00-00", "2020.01.02 00-00.rekey", "notes"]).await;
+        let to = Memory::new();
+
+        assert!(move_old_runs(&from, &to, "cold", "test", DAY).await);
+        assert_eq!(from.list_runs().await.unwrap(), vec!["2020.01.01 00-00", "2020.01.02 00-00.rekey", "notes"]);
+        assert!(to.list_runs().await.unwrap().is_empty());
+
+        write_object(&from, "2020.01.03 00-00", "manifest.json", b"{}").await.unwrap();
+
+        assert!(move_old_runs(&from, &to, "cold", "test", DAY).await);
+        assert_eq!(from.list_runs().await.unwrap(), vec!["2020.01.02 00-00.rekey", "2020.01.03 00-00", "notes"]);
+        assert_eq!(to.list_runs().await.unwrap(), vec!["2020.01.01 00-00"]);
+        assert_eq!(crate::sink::read_object(&to, "2020.01.01 00-00", "manifest.json").await.unwrap(), b"{}");
+    }
+}
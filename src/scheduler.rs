@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Duration, Instant};
+
+use crate::job::BackupJob;
+
+/// Change of the jobs of a running `Scheduler`, see `run_with_updates`.
+pub enum Update {
+    /// Starts a job, or replaces the job with the same name and its interval. A replaced job
+    /// keeps its schedule: the next run is `interval` after the end of the last one.
+    Schedule(Box<BackupJob>, Duration),
+    /// Stops the job with this name, a backup that is already running is finished first. When
+    /// the name is scheduled again meanwhile, the new job waits for that backup.
+    Unschedule(String),
+}
+
+/// Runs every added job in a loop, waiting `interval` between the runs of a job.
+#[derive(Default)]
+pub struct Scheduler {
+    entries: Vec<(BackupJob, Duration)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn add(&mut self, job: BackupJob, interval: Duration) -> &mut Self {
+        self.entries.push((job, interval));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Never returns while at least one job is scheduled.
+    pub async fn run(self) {
+        let (_updates, receiver) = mpsc::channel(1);
+        self.run_with_updates(receiver).await
+    }
+
+    /// Like `run`, while the jobs are changed through `updates`. Jobs that are not named in an
+    /// update keep running untouched. Returns once no job is left and `updates` is closed.
+    pub async fn run_with_updates(self, mut updates: mpsc::Receiver<Update>) {
+        let mut schedules: HashMap<String, Schedule> = HashMap::new();
+        // Unscheduled jobs that can still be finishing a backup
+        let mut stopping: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+        for (job, interval) in self.entries {
+            schedules.insert(job.name().to_string(), Schedule::start(job, interval, None));
+        }
+
+        loop {
+            schedules.retain(|_, schedule| !schedule.handle.is_finished());
+            stopping.retain(|_, handle| !handle.is_finished());
+
+            let update = match updates.recv().await {
+                Some(res) => res,
+                None => break,
+            };
+
+            match update {
+                Update::Schedule(job, interval) => match schedules.get(job.name()) {
+                    Some(schedule) => {
+                        crate::logger::info_string(format!("Schedule of \"{}\" changed, the next backup is {} hours after the last one", job.name(), interval.as_secs_f64() / 3600_f64));
+                        schedule.changes.send_replace(Some((*job, interval)));
+                    }
+                    None => {
+                        crate::logger::info_string(format!("Backups of \"{}\" have been scheduled", job.name()));
+                        // Two runs of the same job would write to the same destinations
+                        let previous = stopping.remove(job.name());
+                        schedules.insert(job.name().to_string(), Schedule::start(*job, interval, previous));
+                    }
+                },
+                Update::Unschedule(name) => {
+                    if let Some(schedule) = schedules.remove(&name) {
+                        crate::logger::info_string(format!("Backups of \"{name}\" have been unscheduled"));
+                        schedule.changes.send_replace(None);
+                        stopping.insert(name, schedule.handle);
+                    }
+                }
+            }
+        }
+
+        for handle in schedules.into_values().map(|schedule| schedule.handle).chain(stopping.into_values()) {
+            handle.await.unwrap_or_default();
+        }
+    }
+}
+
+/// Task that backs up one job, `changes` replaces the job or stops the task with `None`.
+struct Schedule {
+    changes: watch::Sender<Option<(BackupJob, Duration)>>,
+    handle: JoinHandle<()>,
+}
+
+impl Schedule {
+    /// Starts the task once `previous`, the task of the job before it was unscheduled, has ended.
+    fn start(job: BackupJob, interval: Duration, previous: Option<JoinHandle<()>>) -> Self {
+        let (changes, mut receiver) = watch::channel(Some((job, interval)));
+
+        let handle = tokio::spawn(async move {
+            if let Some(previous) = previous {
+                previous.await.unwrap_or_default();
+            }
+
+            // The job can have been changed or unscheduled while waiting
+            let current = receiver.borrow_and_update().clone();
+            if let Some((job, interval)) = current {
+                run_schedule(job, interval, receiver).await
+            }
+        });
+
+        Schedule { changes, handle }
+    }
+}
+
+async fn run_schedule(mut job: BackupJob, mut interval: Duration, mut changes: watch::Receiver<Option<(BackupJob, Duration)>>) {
+    loop {
+        if let Err(err) = job.run_once().await {
+            crate::logger::error_string(format!("Backup of \"{}\" failed: {err}", job.name()));
+        }
+
+        let finished = Instant::now();
+
+        loop {
+            tokio::select! {
+                _ = sleep_until(finished + interval) => break,
+                changed = changes.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+
+                    match changes.borrow_and_update().clone() {
+                        Some((new_job, new_interval)) => {
+                            job = new_job;
+                            interval = new_interval;
+                        }
+                        None => return,
+                    }
+                }
+            }
+        }
+    }
+}
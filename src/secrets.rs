@@ -0,0 +1,314 @@
+//! Connection strings that keep their secrets out of the config: `${VAR}` is replaced with the
+//! environment variable `VAR`, the whole string or its password can be read from files (Docker
+//! and Kubernetes secrets). They are only resolved right before connecting, a job never holds them, and the
+//! passwords are masked in the log from then on.
+
+use std::{fmt, path::{Path, PathBuf}};
+
+use crate::{Error, Result};
+
+/// Connection string of a job as it is configured.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionUrl {
+    url: Option<String>,
+    url_file: Option<PathBuf>,
+    password_file: Option<PathBuf>,
+}
+
+impl ConnectionUrl {
+    /// Connection string that can contain `${VAR}`.
+    pub fn new(url: impl Into<String>) -> Self {
+        ConnectionUrl { url: Some(url.into()), ..ConnectionUrl::default() }
+    }
+
+    /// Connection string read from the file `path`, surrounding whitespace is ignored.
+    pub fn from_file(path: impl Into<PathBuf>) -> Self {
+        ConnectionUrl { url_file: Some(path.into()), ..ConnectionUrl::default() }
+    }
+
+    /// Password read from the file `path`, it replaces the password of the connection string.
+    pub fn password_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.password_file = Some(path.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.url_file.is_none() && self.url.as_deref().is_none_or(|url| url.trim().is_empty())
+    }
+
+    /// The connection string with the variables and files filled in, never log it.
+    pub fn resolve(&self) -> Result<String> {
+        let url = match (&self.url, &self.url_file) {
+            (_, Some(path)) => read_secret(path)?,
+            (Some(url), None) => interpolate(url)?,
+            (None, None) => return Err(Error::Config("connection url is empty".to_string())),
+        };
+
+        let url = match &self.password_file {
+            Some(path) => {
+                let password = read_secret(path)?;
+                crate::logger::add_secret(&password);
+                with_password(&url, &password)?
+            }
+            None => url,
+        };
+
+        if let Some(password) = password(&url) {
+            crate::logger::add_secret(password);
+        }
+
+        Ok(url)
+    }
+
+    /// Checks what is known without reading the environment and the files: the `${VAR}`s are
+    /// well formed and a `passwordFile` has a user name to go with. Returns the connection string
+    /// when it has no variables, `None` when it is only known when connecting.
+    pub fn template(&self) -> Result<Option<String>> {
+        let url = match (&self.url, &self.url_file) {
+            (_, Some(_)) => return Ok(None),
+            (Some(url), None) => url,
+            (None, None) => return Err(Error::Config("connection url is empty".to_string())),
+        };
+
+        let mut variables = false;
+        let template = substitute(url, |_| {
+            variables = true;
+            Ok("placeholder".to_string())
+        })?;
+
+        if self.password_file.is_some() {
+            with_password(&template, "placeholder")?;
+        }
+
+        match variables {
+            true => Ok(None),
+            false => Ok(Some(template)),
+        }
+    }
+}
+
+impl From<String> for ConnectionUrl {
+    fn from(url: String) -> Self {
+        ConnectionUrl::new(url)
+    }
+}
+
+impl From<&str> for ConnectionUrl {
+    fn from(url: &str) -> Self {
+        ConnectionUrl::new(url)
+    }
+}
+
+impl From<&String> for ConnectionUrl {
+    fn from(url: &String) -> Self {
+        ConnectionUrl::new(url.as_str())
+    }
+}
+
+/// The configured form, without the values of the variables and files.
+impl fmt::Display for ConnectionUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.url, &self.url_file) {
+            (_, Some(path)) => write!(f, "<{}>", path.display())?,
+            (Some(url), None) => write!(f, "{url}")?,
+            (None, None) => {}
+        }
+
+        match &self.password_file {
+            Some(path) => write!(f, " (password from <{}>)", path.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Replaces every `${VAR}` of `text` with the environment variable `VAR`, `$${` is a literal `${`.
+pub fn interpolate(text: &str) -> Result<String> {
+    substitute(text, |variable| match std::env::var(variable) {
+        Ok(res) => Ok(res),
+        Err(_) => Err(Error::Config(format!("environment variable \"{variable}\" is not set"))),
+    })
+}
+
+/// Replaces every `${VAR}` of `text` with `value(VAR)`.
+fn substitute(text: &str, mut value: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            res.push_str(&rest[..start - 1]);
+            res.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        res.push_str(&rest[..start]);
+
+        let end = match rest[start + 2..].find('}') {
+            Some(res) => start + 2 + res,
+            None => return Err(Error::Config("\"${\" is not closed with \"}\"".to_string())),
+        };
+
+        let variable = &rest[start + 2..end];
+
+        if variable.is_empty() || !variable.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::Config(format!("\"${{{variable}}}\" is not an environment variable name")));
+        }
+
+        res.push_str(&value(variable)?);
+
+        rest = &rest[end + 1..];
+    }
+
+    res.push_str(rest);
+    Ok(res)
+}
+
+fn read_secret(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(res) => Ok(res.trim().to_string()),
+        Err(err) => Err(Error::Config(format!("Failed to read secret file \"{}\": {err}", path.display()))),
+    }
+}
+
+/// Password in the user info of `url`, as it is written there.
+fn password(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let authority = &rest[..rest.find(['/', '?']).unwrap_or(rest.len())];
+    let userinfo = &authority[..authority.rfind('@')?];
+    userinfo.split_once(':').map(|(_, password)| password).filter(|password| !password.is_empty())
+}
+
+/// `url` with `password` (percent encoded) as the password of its user.
+fn with_password(url: &str, password: &str) -> Result<String> {
+    let (scheme, rest) = match url.split_once("://") {
+        Some(res) => res,
+        None => return Err(Error::Config("connection url has no scheme".to_string())),
+    };
+
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+
+    let user = match rest[..authority_end].rfind('@') {
+        Some(at) => rest[..at].split(':').next().unwrap_or_default(),
+        None => "",
+    };
+
+    if user.is_empty() {
+        return Err(Error::Config("passwordFile needs a user name in the connection url".to_string()));
+    }
+
+    let host = match rest[..authority_end].rfind('@') {
+        Some(at) => &rest[at + 1..],
+        None => rest,
+    };
+
+    Ok(format!("{scheme}://{user}:{}@{host}", percent_encode(password)))
+}
+
+/// Escapes everything but the unreserved characters of RFC 3986.
+pub(crate) fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// File in the temp directory with `content`, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("mongo_backuper-{}-{name}", std::process::id()));
+            std::fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).unwrap_or_default();
+        }
+    }
+
+    #[test]
+    fn interpolate_variables() {
+        std::env::set_var("MONGO_BACKUPER_TEST_USER", "backup");
+
+        assert_eq!(interpolate("mongodb://${MONGO_BACKUPER_TEST_USER}@h/${MONGO_BACKUPER_TEST_USER}").unwrap(), "mongodb://backup@h/backup");
+        assert_eq!(interpolate("a$${MONGO_BACKUPER_TEST_USER}b").unwrap(), "a${MONGO_BACKUPER_TEST_USER}b");
+        assert_eq!(interpolate("$$${MONGO_BACKUPER_TEST_USER}").unwrap(), "$${MONGO_BACKUPER_TEST_USER}");
+        assert_eq!(interpolate("no variables $ {}").unwrap(), "no variables $ {}");
+    }
+
+    #[test]
+    fn interpolate_errors() {
+        assert!(matches!(interpolate("${MONGO_BACKUPER_TEST_UNSET}"), Err(Error::Config(message)) if message.contains("is not set")));
+        assert!(matches!(interpolate("${A-B}"), Err(Error::Config(message)) if message.contains("not an environment variable name")));
+        assert!(matches!(interpolate("${}"), Err(Error::Config(_))));
+        assert!(matches!(interpolate("${OPEN"), Err(Error::Config(message)) if message.contains("not closed")));
+    }
+
+    #[test]
+    fn password_of_url() {
+        assert_eq!(password("mongodb://u:secret@h:27017/db"), Some("secret"));
+        assert_eq!(password("mongodb://u:p@ss@h"), Some("p@ss"));
+        assert_eq!(password("mongodb://u@h"), None);
+        assert_eq!(password("mongodb://u:@h"), None);
+        assert_eq!(password("mongodb://h/db?user=a:b@c"), None);
+        assert_eq!(password("not a url"), None);
+    }
+
+    #[test]
+    fn replace_password() {
+        assert_eq!(with_password("mongodb://u@h:27017/db?tls=true", "p@ss w/rd").unwrap(), "mongodb://u:p%40ss%20w%2Frd@h:27017/db?tls=true");
+        assert_eq!(with_password("mongodb://u:old@h", "new").unwrap(), "mongodb://u:new@h");
+        assert!(with_password("mongodb://h", "p").is_err());
+        assert!(with_password("mongodb://:p@h", "p").is_err());
+        assert!(with_password("h:27017", "p").is_err());
+    }
+
+    #[test]
+    fn percent_encoding() {
+        assert_eq!(percent_encode("aZ09-._~"), "aZ09-._~");
+        assert_eq!(percent_encode("a:b/@%é"), "a%3Ab%2F%40%25%C3%A9");
+    }
+
+    #[test]
+    fn resolve_files() {
+        let url_file = TempFile::new("url", "  mongodb://u:from-file@h:27017\n");
+        let password_file = TempFile::new("password", "p:w@rd!\n");
+
+        assert_eq!(ConnectionUrl::from_file(&url_file.0).resolve().unwrap(), "mongodb://u:from-file@h:27017");
+        assert_eq!(
+            ConnectionUrl::new("mongodb://u@h:27017").password_file(&password_file.0).resolve().unwrap(),
+            "mongodb://u:p%3Aw%40rd%21@h:27017",
+        );
+        assert_eq!(
+            ConnectionUrl::from_file(&url_file.0).password_file(&password_file.0).resolve().unwrap(),
+            "mongodb://u:p%3Aw%40rd%21@h:27017",
+        );
+        assert!(ConnectionUrl::from_file("/nonexistent/mongo_backuper").resolve().is_err());
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(ConnectionUrl::new("mongodb://u:p@h").template().unwrap().as_deref(), Some("mongodb://u:p@h"));
+        assert_eq!(ConnectionUrl::new("mongodb://u:$${x}@h").template().unwrap().as_deref(), Some("mongodb://u:${x}@h"));
+        assert_eq!(ConnectionUrl::new("mongodb://u:${MONGO_BACKUPER_TEST_UNSET}@h").template().unwrap(), None);
+        assert_eq!(ConnectionUrl::from_file("/nonexistent/mongo_backuper").template().unwrap(), None);
+        assert!(ConnectionUrl::new("mongodb://u@h").password_file("/nonexistent").template().is_ok());
+        assert!(ConnectionUrl::new("mongodb://h").password_file("/nonexistent").template().is_err());
+    }
+
+    #[test]
+    fn display_hides_values() {
+        assert_eq!(ConnectionUrl::new("mongodb://u:${P}@h").to_string(), "mongodb://u:${P}@h");
+        assert_eq!(ConnectionUrl::from_file("/run/url").password_file("/run/password").to_string(), "</run/url> (password from </run/password>)");
+    }
+}
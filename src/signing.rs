@@ -0,0 +1,109 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, Verifier};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::decode_key;
+use crate::{Error, Result};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+pub const ALGORITHM: &str = "ed25519";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SigningConfig {
+    /// Ed25519 private key: PKCS#8 PEM (`openssl genpkey -algorithm ed25519`) or the 32 byte seed raw / hex / base64.
+    #[serde(rename = "keyFile")]
+    pub key_file: Option<String>,
+    /// Public key to verify with where the private key is not available, PEM or 32 bytes raw / hex / base64.
+    #[serde(rename = "publicKeyFile")]
+    pub public_key_file: Option<String>,
+}
+
+impl SigningConfig {
+    /// Key that signs the manifests, `None` when only the public key is configured.
+    pub fn signing_key(&self, name: &str) -> Result<Option<SigningKey>> {
+        match (&self.key_file, &self.public_key_file) {
+            (Some(path), _) => Ok(Some(read_signing_key(path)?)),
+            (None, Some(_)) => Ok(None),
+            (None, None) => Err(Error::Config(format!("Signing of \"{name}\" needs \"keyFile\" or \"publicKeyFile\""))),
+        }
+    }
+
+    pub fn verifying_key(&self, name: &str) -> Result<VerifyingKey> {
+        match &self.public_key_file {
+            Some(path) => read_verifying_key(path),
+            None => match self.signing_key(name)? {
+                Some(key) => Ok(key.verifying_key()),
+                None => Err(Error::Config(format!("Signing of \"{name}\" needs \"keyFile\" or \"publicKeyFile\""))),
+            },
+        }
+    }
+}
+
+/// Content of `manifest.sig`: the signature of the exact bytes of `manifest.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestSignature {
+    pub algorithm: String,
+    /// Hex public key of the signer.
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    pub signature: String,
+}
+
+pub fn read_signing_key(path: &str) -> Result<SigningKey> {
+    let data = std::fs::read(path)
+        .map_err(|err| Error::Config(format!("Failed to read signing key \"{path}\": {err}")))?;
+
+    match std::str::from_utf8(&data) {
+        Ok(text) if text.contains("-----BEGIN") => SigningKey::from_pkcs8_pem(text)
+            .map_err(|err| Error::Config(format!("Invalid signing key \"{path}\": {err}"))),
+        _ => Ok(SigningKey::from_bytes(&decode_key(&data, "Signing key")?)),
+    }
+}
+
+pub fn read_verifying_key(path: &str) -> Result<VerifyingKey> {
+    let data = std::fs::read(path)
+        .map_err(|err| Error::Config(format!("Failed to read public key \"{path}\": {err}")))?;
+
+    match std::str::from_utf8(&data) {
+        Ok(text) if text.contains("-----BEGIN") => VerifyingKey::from_public_key_pem(text)
+            .map_err(|err| Error::Config(format!("Invalid public key \"{path}\": {err}"))),
+        _ => VerifyingKey::from_bytes(&decode_key(&data, "Public key")?)
+            .map_err(|err| Error::Config(format!("Invalid public key \"{path}\": {err}"))),
+    }
+}
+
+/// Signs the bytes of a manifest, returns the content of `manifest.sig`.
+pub fn sign(key: &SigningKey, manifest: &[u8]) -> Result<Vec<u8>> {
+    let signature = ManifestSignature {
+        algorithm: ALGORITHM.to_string(),
+        public_key: hex::encode(key.verifying_key().as_bytes()),
+        signature: STANDARD.encode(key.sign(manifest).to_bytes()),
+    };
+
+    Ok(serde_json::to_vec_pretty(&signature)?)
+}
+
+/// Checks that `signature_file` is a signature of `manifest` by `trusted`.
+pub fn check(trusted: &VerifyingKey, manifest: &[u8], signature_file: &[u8]) -> Result<()> {
+    let signature: ManifestSignature = serde_json::from_slice(signature_file)?;
+
+    if signature.algorithm != ALGORITHM {
+        return Err(Error::Corrupt(format!("Unknown signature algorithm \"{}\"", signature.algorithm)));
+    }
+
+    let trusted_hex = hex::encode(trusted.as_bytes());
+    if signature.public_key != trusted_hex {
+        return Err(Error::Corrupt(format!("Manifest is signed by {}, expected {trusted_hex}", signature.public_key)));
+    }
+
+    let bytes = STANDARD.decode(&signature.signature)
+        .map_err(|err| Error::Corrupt(format!("Invalid signature: {err}")))?;
+    let signature = Signature::from_slice(&bytes)
+        .map_err(|err| Error::Corrupt(format!("Invalid signature: {err}")))?;
+
+    trusted.verify(manifest, &signature)
+        .map_err(|_| Error::Corrupt("Manifest does not match its signature".to_string()))
+}
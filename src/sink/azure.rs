@@ -0,0 +1,351 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use quick_xml::events::Event;
+use reqwest::{Method, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::{OffsetDateTime, Weekday};
+
+use super::http::{check_status, uri_encode};
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::{Error, Result};
+
+const API_VERSION: &str = "2021-08-06";
+const BLOCK_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AzureConfig {
+    pub account: String,
+    pub container: String,
+    #[serde(default)]
+    pub prefix: String,
+    /// Blob service url, defaults to `https://<account>.blob.core.windows.net`.
+    /// For Azurite use `http://127.0.0.1:10000/devstoreaccount1`.
+    pub endpoint: Option<String>,
+    /// Base64 shared key of the storage account.
+    #[serde(rename = "accountKey")]
+    pub account_key: Option<String>,
+    /// SAS token of the container (with or without the leading `?`), used instead of `accountKey`.
+    #[serde(rename = "sasToken")]
+    pub sas_token: Option<String>,
+}
+
+/// Stores runs as block blobs `<prefix>/<name>/<run>/<path>` of a container.
+#[derive(Clone)]
+pub struct Azure {
+    config: AzureConfig,
+    endpoint: String,
+    prefix: String,
+    key: Option<Vec<u8>>,
+    client: reqwest::Client,
+}
+
+impl Azure {
+    pub fn new(config: AzureConfig, name: &str) -> Result<Self> {
+        if config.account.is_empty() || config.container.is_empty() {
+            return Err(Error::Config(format!("Azure \"account\" and \"container\" of \"{name}\" can not be empty")));
+        }
+
+        let key = match (&config.account_key, &config.sas_token) {
+            (Some(key), _) => Some(STANDARD.decode(key.trim())
+                .map_err(|err| Error::Config(format!("Invalid Azure account key of \"{name}\": {err}")))?),
+            (None, Some(_)) => None,
+            (None, None) => {
+                return Err(Error::Config(format!("Azure storage of \"{name}\" needs \"accountKey\" or \"sasToken\"")));
+            }
+        };
+
+        let endpoint = match &config.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://{}.blob.core.windows.net", config.account),
+        };
+
+        let prefix = match config.prefix.trim_matches('/') {
+            "" => format!("{name}/"),
+            prefix => format!("{prefix}/{name}/"),
+        };
+
+        Ok(Azure { config, endpoint, prefix, key, client: reqwest::Client::new() })
+    }
+
+    fn blob(&self, run: &str, path: &str) -> String {
+        format!("{}{run}/{path}", self.prefix)
+    }
+
+    async fn request(&self, method: Method, blob: Option<&str>, query: &[(&str, &str)], headers: &[(&str, &str)], body: Vec<u8>) -> Result<Response> {
+        check_status(self.send(method, blob, query, headers, body).await?).await
+    }
+
+    /// Signed request without checking the status of the response.
+    async fn send(&self, method: Method, blob: Option<&str>, query: &[(&str, &str)], headers: &[(&str, &str)], body: Vec<u8>) -> Result<Response> {
+        let mut url = format!("{}/{}", self.endpoint, self.config.container);
+        if let Some(blob) = blob {
+            url.push('/');
+            url.push_str(&uri_encode(blob, false));
+        }
+
+        let mut pairs: Vec<String> = query.iter()
+            .map(|(name, value)| format!("{name}={}", uri_encode(value, true)))
+            .collect();
+        if let (None, Some(sas)) = (&self.key, &self.config.sas_token) {
+            pairs.push(sas.trim_start_matches('?').to_string());
+        }
+        if !pairs.is_empty() {
+            url.push('?');
+            url.push_str(&pairs.join("&"));
+        }
+
+        let date = http_date(OffsetDateTime::now_utc());
+        let mut request = self.client.request(method.clone(), &url)
+            .header("x-ms-date", &date)
+            .header("x-ms-version", API_VERSION);
+
+        let mut ms_headers = vec![("x-ms-date".to_string(), date), ("x-ms-version".to_string(), API_VERSION.to_string())];
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+            ms_headers.push((name.to_lowercase(), value.to_string()));
+        }
+
+        if let Some(key) = &self.key {
+            ms_headers.sort();
+            let canonical_headers: String = ms_headers.iter().map(|(name, value)| format!("{name}:{value}\n")).collect();
+
+            let path = url.split_once("://")
+                .and_then(|(_, rest)| rest.find('/').map(|index| &rest[index..]))
+                .unwrap_or("/");
+            let path = path.split('?').next().unwrap_or(path);
+
+            let mut canonical_query: Vec<(String, &str)> = query.iter().map(|(name, value)| (name.to_lowercase(), *value)).collect();
+            canonical_query.sort();
+            let canonical_resource = format!("/{}{path}", self.config.account)
+                + &canonical_query.iter().map(|(name, value)| format!("\n{name}:{value}")).collect::<String>();
+
+            let content_length = match body.len() {
+                0 => String::new(),
+                length => length.to_string(),
+            };
+
+            let string_to_sign = format!("{method}\n\n\n{content_length}\n\n\n\n\n\n\n\n\n{canonical_headers}{canonical_resource}");
+
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+            mac.update(string_to_sign.as_bytes());
+            let signature = STANDARD.encode(mac.finalize().into_bytes());
+
+            request = request.header("authorization", format!("SharedKey {}:{signature}", self.config.account));
+        }
+
+        Ok(request.body(body).send().await?)
+    }
+
+    /// Blob names under `prefix`; with `delimiter` only the next level of "directories" is returned.
+    async fn list(&self, prefix: &str, delimiter: bool) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut marker: Option<String> = None;
+
+        loop {
+            let mut query = vec![("restype", "container"), ("comp", "list"), ("prefix", prefix)];
+            if delimiter {
+                query.push(("delimiter", "/"));
+            }
+            if let Some(marker) = &marker {
+                query.push(("marker", marker.as_str()));
+            }
+
+            let body = self.request(Method::GET, None, &query, &[], Vec::new()).await?.text().await?;
+            let listing = parse_list(&body)?;
+
+            match delimiter {
+                true => names.extend(listing.prefixes),
+                false => names.extend(listing.blobs),
+            }
+
+            match listing.next_marker {
+                Some(next) => marker = Some(next),
+                None => return Ok(names),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BackupSink for Azure {
+    fn describe(&self) -> String {
+        format!("{}/{}/{}", self.endpoint, self.config.container, self.prefix)
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        Ok(Box::new(AzureBlob {
+            sink: self.clone(),
+            blob: self.blob(run, path),
+            buffer: Vec::new(),
+            blocks: Vec::new(),
+        }))
+    }
+
+    async fn finalize(&self, _run: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        let mut runs: Vec<String> = self.list(&self.prefix, true).await?
+            .iter()
+            .filter_map(|prefix| prefix.strip_prefix(&self.prefix))
+            .map(|run| run.trim_end_matches('/').to_string())
+            .collect();
+        runs.sort();
+        Ok(runs)
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        let run_prefix = format!("{}{run}/", self.prefix);
+        let mut objects: Vec<String> = self.list(&run_prefix, false).await?
+            .iter()
+            .filter_map(|blob| blob.strip_prefix(&run_prefix))
+            .map(|path| path.to_string())
+            .collect();
+        objects.sort();
+        Ok(objects)
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let response = self.request(Method::GET, Some(&self.blob(run, path)), &[], &[], Vec::new()).await?;
+        Ok(Box::new(AzureReader { response }))
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        let response = self.send(Method::DELETE, Some(&self.blob(run, path)), &[], &[], Vec::new()).await?;
+
+        if response.status() != StatusCode::NOT_FOUND {
+            check_status(response).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        for blob in self.list(&format!("{}{run}/", self.prefix), false).await? {
+            self.request(Method::DELETE, Some(&blob), &[], &[], Vec::new()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Uploads with Put Blob when the object fits into one block, with Put Block + Put Block List otherwise.
+struct AzureBlob {
+    sink: Azure,
+    blob: String,
+    buffer: Vec<u8>,
+    blocks: Vec<String>,
+}
+
+impl AzureBlob {
+    async fn put_block(&mut self) -> Result<()> {
+        let block_id = STANDARD.encode(format!("{:08}", self.blocks.len()));
+        let body = std::mem::take(&mut self.buffer);
+
+        self.sink.request(Method::PUT, Some(&self.blob), &[("comp", "block"), ("blockid", &block_id)], &[], body).await?;
+        self.blocks.push(block_id);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ObjectWriter for AzureBlob {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.buffer.extend_from_slice(buf);
+
+        if self.buffer.len() >= BLOCK_SIZE {
+            self.put_block().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.blocks.is_empty() {
+            let body = std::mem::take(&mut self.buffer);
+            self.sink.request(Method::PUT, Some(&self.blob), &[], &[("x-ms-blob-type", "BlockBlob")], body).await?;
+            return Ok(());
+        }
+
+        if !self.buffer.is_empty() {
+            self.put_block().await?;
+        }
+
+        let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        for block_id in &self.blocks {
+            body.push_str(&format!("<Latest>{block_id}</Latest>"));
+        }
+        body.push_str("</BlockList>");
+
+        self.sink.request(Method::PUT, Some(&self.blob), &[("comp", "blocklist")], &[], body.into_bytes()).await?;
+
+        Ok(())
+    }
+}
+
+struct AzureReader {
+    response: Response,
+}
+
+#[async_trait]
+impl ObjectReader for AzureReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.response.chunk().await?.map(|chunk| chunk.to_vec()))
+    }
+}
+
+/// RFC 1123 date used by the `x-ms-date` header.
+fn http_date(date: OffsetDateTime) -> String {
+    let weekday = match date.weekday() {
+        Weekday::Monday => "Mon",
+        Weekday::Tuesday => "Tue",
+        Weekday::Wednesday => "Wed",
+        Weekday::Thursday => "Thu",
+        Weekday::Friday => "Fri",
+        Weekday::Saturday => "Sat",
+        Weekday::Sunday => "Sun",
+    };
+    let month = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"][date.month() as usize - 1];
+
+    format!("{weekday}, {:02} {month} {} {:02}:{:02}:{:02} GMT", date.day(), date.year(), date.hour(), date.minute(), date.second())
+}
+
+#[derive(Default)]
+struct Listing {
+    blobs: Vec<String>,
+    prefixes: Vec<String>,
+    next_marker: Option<String>,
+}
+
+fn parse_list(body: &str) -> Result<Listing> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    let mut listing = Listing::default();
+    let mut path: Vec<String> = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => path.push(String::from_utf8_lossy(start.local_name().as_ref()).to_string()),
+            Ok(Event::End(_)) => {
+                path.pop();
+            }
+            Ok(Event::Text(text)) => {
+                let value = text.unescape().map_err(|err| Error::Storage(err.to_string()))?.to_string();
+                let parent = path.len().checked_sub(2).and_then(|index| path.get(index)).map(|name| name.as_str());
+
+                match (parent, path.last().map(|name| name.as_str())) {
+                    (Some("Blob"), Some("Name")) => listing.blobs.push(value),
+                    (Some("BlobPrefix"), Some("Name")) => listing.prefixes.push(value),
+                    (_, Some("NextMarker")) if !value.is_empty() => listing.next_marker = Some(value),
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => return Ok(listing),
+            Err(err) => return Err(Error::Storage(format!("Invalid Azure response: {err}"))),
+            _ => {}
+        }
+    }
+}
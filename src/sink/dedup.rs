@@ -0,0 +1,654 @@
+use async_trait::async_trait;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::{HashSet, VecDeque}, io::{Read, Write}, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, MutexGuard}};
+use time::OffsetDateTime;
+use tokio::time::Duration;
+
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::job::Compression;
+use crate::report::MANIFEST_FILE;
+use crate::signing::SIGNATURE_FILE;
+use crate::{Error, Result};
+
+/// Run of the inner sink that holds the chunks, as `<first two hex digits>/<sha256>`.
+pub const CHUNKS: &str = "chunks";
+
+/// Run of the inner sink whose only object is the lock of the chunks, see `Dedup`.
+pub const LOCK: &str = "chunks.lock";
+const LOCK_FILE: &str = "lock.json";
+
+/// A lock that was not renewed for this long (in seconds) is left over from a failed run or process.
+const LOCK_TIMEOUT: i64 = 15 * 60;
+/// A held lock is renewed when it is older than this (in seconds).
+const LOCK_RENEW: i64 = 60;
+/// Wait between two looks at a lock held by another process.
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(0);
+
+/// Start of every index object, anything else is read as a plain object.
+const INDEX_MAGIC: &[u8] = b"MBDEDUP1\n";
+
+const MIN_CHUNK: u32 = 64 * 1024;
+const AVG_CHUNK: u32 = 256 * 1024;
+const MAX_CHUNK: u32 = 1024 * 1024;
+
+const TAG_RAW: u8 = b'N';
+const TAG_GZIP: u8 = b'G';
+
+/// Stores the objects of another sink as content-defined chunks that are kept once
+/// by their sha256. Every object of a run becomes an index listing its chunks, the
+/// manifest and its signature are stored as they are. `after_prune` removes the chunks
+/// no run references anymore.
+///
+/// Writing a run and removing chunks take turns through a lock object in the inner sink,
+/// also between processes: a run holds it from its first object until `finalize`.
+/// Removing chunks is skipped while another process holds the lock, writing waits for it.
+#[derive(Clone)]
+pub struct Dedup {
+    inner: Arc<dyn BackupSink>,
+    compression: Compression,
+    owner: String,
+    state: Arc<Mutex<LockState>>,
+}
+
+#[derive(Default)]
+struct LockState {
+    /// Ids of the stored chunks, listed when the lock was taken.
+    known: HashSet<String>,
+    /// When the lock was written last, `None` while it is not held.
+    renewed: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Lock {
+    owner: String,
+    renewed: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Index {
+    chunks: Vec<ChunkRef>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ChunkRef {
+    id: String,
+    size: usize,
+}
+
+impl Dedup {
+    /// Chunks are compressed one by one with `compression`.
+    pub fn new(inner: Arc<dyn BackupSink>, compression: Compression) -> Self {
+        let owner = format!("{}-{}-{}", std::process::id(), OffsetDateTime::now_utc().unix_timestamp_nanos(), NEXT_OWNER.fetch_add(1, Ordering::Relaxed));
+        Dedup { inner, compression, owner, state: Arc::new(Mutex::new(LockState::default())) }
+    }
+
+    pub fn inner(&self) -> &Arc<dyn BackupSink> {
+        &self.inner
+    }
+
+    fn state(&self) -> Result<MutexGuard<'_, LockState>> {
+        self.state.lock().map_err(|_| Error::Storage("Chunk list is poisoned".to_string()))
+    }
+
+    /// When the lock was written last, `None` when it is not held or has expired meanwhile.
+    fn held(&self) -> Result<Option<i64>> {
+        let mut state = self.state()?;
+
+        if state.renewed.is_some_and(|renewed| unix_now() - renewed >= LOCK_TIMEOUT) {
+            state.renewed = None;
+            state.known.clear();
+        }
+
+        Ok(state.renewed)
+    }
+
+    /// Takes the lock of the chunks, waiting while another process holds it, or renews the
+    /// held lock. Taking it lists the stored chunks again, they can have been removed meanwhile.
+    async fn lock_chunks(&self) -> Result<()> {
+        match self.held()? {
+            Some(renewed) if unix_now() - renewed < LOCK_RENEW => return Ok(()),
+            Some(_) => return self.renew_lock().await,
+            None => {}
+        }
+
+        let mut waiting = false;
+
+        loop {
+            if let Some(lock) = self.read_lock().await? {
+                if lock.owner != self.owner && unix_now() - lock.renewed < LOCK_TIMEOUT {
+                    if !waiting {
+                        crate::logger::debug_string(format!("Waiting for another process to release the chunks of {}", self.inner.describe()));
+                        waiting = true;
+                    }
+
+                    tokio::time::sleep(LOCK_WAIT).await;
+                    continue;
+                }
+            }
+
+            let renewed = self.write_lock().await?;
+
+            // Another process can have taken it at the same time, the last write wins
+            if self.read_lock().await?.is_some_and(|lock| lock.owner == self.owner) {
+                let known = self.list_chunks().await?;
+                let mut state = self.state()?;
+                state.known = known;
+                state.renewed = Some(renewed);
+                return Ok(());
+            }
+        }
+    }
+
+    async fn renew_lock(&self) -> Result<()> {
+        if self.read_lock().await?.is_none_or(|lock| lock.owner != self.owner) {
+            *self.state()? = LockState::default();
+            return Err(Error::Storage(format!("Lock of the chunks of {} was taken by another process", self.inner.describe())));
+        }
+
+        let renewed = self.write_lock().await?;
+        self.state()?.renewed = Some(renewed);
+        Ok(())
+    }
+
+    /// Releases the lock when it is held.
+    async fn unlock_chunks(&self) -> Result<()> {
+        if std::mem::take(&mut *self.state()?).renewed.is_none() {
+            return Ok(());
+        }
+
+        // An expired lock can belong to another process by now
+        match self.read_lock().await? {
+            Some(lock) if lock.owner == self.owner => self.inner.delete_run(LOCK).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn read_lock(&self) -> Result<Option<Lock>> {
+        match super::read_object(self.inner.as_ref(), LOCK, LOCK_FILE).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) => match self.inner.list_runs().await?.iter().any(|run| run == LOCK) {
+                true => Err(err),
+                false => Ok(None),
+            },
+        }
+    }
+
+    /// Writes the lock of this sink, returns the time it was written at.
+    async fn write_lock(&self) -> Result<i64> {
+        let lock = Lock { owner: self.owner.clone(), renewed: unix_now() };
+        super::write_object(self.inner.as_ref(), LOCK, LOCK_FILE, &serde_json::to_vec(&lock)?).await?;
+        Ok(lock.renewed)
+    }
+
+    async fn list_chunks(&self) -> Result<HashSet<String>> {
+        if !self.inner.list_runs().await?.iter().any(|run| run == CHUNKS) {
+            return Ok(HashSet::new());
+        }
+
+        Ok(self.inner.list_objects(CHUNKS).await?
+            .iter()
+            .filter_map(|object| object.split_once('/'))
+            .map(|(_, id)| id.to_string())
+            .collect())
+    }
+
+    /// Stores one chunk unless a chunk with the same content exists, returns whether it was new.
+    async fn store(&self, data: &[u8]) -> Result<(ChunkRef, bool)> {
+        let id = hex::encode(Sha256::digest(data));
+        let chunk = ChunkRef { id: id.clone(), size: data.len() };
+
+        self.lock_chunks().await?;
+        if self.state()?.known.contains(&id) {
+            return Ok((chunk, false));
+        }
+
+        let stored = match self.compression {
+            Compression::None => [&[TAG_RAW], data].concat(),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(vec![TAG_GZIP], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+        };
+
+        super::write_object(self.inner.as_ref(), CHUNKS, &chunk_path(&id), &stored).await?;
+
+        self.state()?.known.insert(id);
+
+        Ok((chunk, true))
+    }
+
+    /// Deletes every chunk that no index of the remaining runs refers to. Skipped while another
+    /// process holds the lock. Never called while this sink writes a run, its new chunks have no index yet.
+    async fn collect_garbage(&self) -> Result<()> {
+        let held = self.held()?.is_some();
+
+        if !held {
+            if let Some(lock) = self.read_lock().await? {
+                if lock.owner != self.owner && unix_now() - lock.renewed < LOCK_TIMEOUT {
+                    crate::logger::debug_string(format!("Chunks of {} are in use by another process, they are cleaned up later", self.inner.describe()));
+                    return Ok(());
+                }
+            }
+        }
+
+        self.lock_chunks().await?;
+
+        let result = self.remove_unreferenced().await;
+
+        // A lock held before belongs to a run that is still written
+        match held {
+            true => result,
+            false => {
+                let unlocked = self.unlock_chunks().await;
+                result.and(unlocked)
+            }
+        }
+    }
+
+    async fn remove_unreferenced(&self) -> Result<()> {
+        let mut referenced = HashSet::new();
+
+        for run in self.list_runs().await? {
+            self.lock_chunks().await?;
+
+            for object in self.inner.list_objects(&run).await? {
+                if is_passthrough(&object) {
+                    continue;
+                }
+
+                let data = super::read_object(self.inner.as_ref(), &run, &object).await?;
+                if let Some(index) = parse_index(&data)? {
+                    referenced.extend(index.chunks.into_iter().map(|chunk| chunk.id));
+                }
+            }
+        }
+
+        let stored = self.list_chunks().await?;
+        let mut removed = 0;
+
+        for id in stored.iter().filter(|id| !referenced.contains(*id)) {
+            self.lock_chunks().await?;
+            self.inner.delete_object(CHUNKS, &chunk_path(id)).await?;
+            removed += 1;
+        }
+
+        self.state()?.known = stored.intersection(&referenced).cloned().collect();
+
+        if removed > 0 {
+            crate::logger::debug_string(format!("Removed {removed} unreferenced chunks from {}", self.inner.describe()));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BackupSink for Dedup {
+    fn describe(&self) -> String {
+        format!("{} (deduplicated)", self.inner.describe())
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        if is_passthrough(path) {
+            return self.inner.create_object(run, path).await;
+        }
+
+        self.lock_chunks().await?;
+
+        Ok(Box::new(DedupWriter {
+            dedup: self.clone(),
+            run: run.to_string(),
+            path: path.to_string(),
+            buffer: Vec::new(),
+            chunks: Vec::new(),
+            new: 0,
+        }))
+    }
+
+    /// Releases the lock taken for writing the run.
+    async fn finalize(&self, run: &str) -> Result<()> {
+        let finalized = self.inner.finalize(run).await;
+        let unlocked = self.unlock_chunks().await;
+        finalized.and(unlocked)
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        let mut runs = self.inner.list_runs().await?;
+        runs.retain(|run| run != CHUNKS && run != LOCK);
+        Ok(runs)
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        self.inner.list_objects(run).await
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let mut reader = self.inner.open_object(run, path).await?;
+
+        if is_passthrough(path) {
+            return Ok(reader);
+        }
+
+        let mut head = Vec::new();
+        while head.len() < INDEX_MAGIC.len() {
+            match reader.read_chunk().await? {
+                Some(chunk) => head.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+
+        // Objects written before the sink was deduplicated are read as they are
+        if !head.starts_with(INDEX_MAGIC) {
+            return Ok(Box::new(PlainReader { head: Some(head), reader }));
+        }
+
+        while let Some(chunk) = reader.read_chunk().await? {
+            head.extend_from_slice(&chunk);
+        }
+
+        let index = match parse_index(&head)? {
+            Some(res) => res,
+            None => return Err(Error::Corrupt(format!("Index of \"{run}/{path}\" is invalid"))),
+        };
+
+        Ok(Box::new(DedupReader { inner: self.inner.clone(), chunks: index.chunks.into() }))
+    }
+
+    /// Copies the index, the chunks are shared anyway.
+    async fn link_object(&self, from_run: &str, to_run: &str, path: &str) -> Result<bool> {
+        if is_passthrough(path) || !self.inner.list_objects(from_run).await?.iter().any(|object| object == path) {
+            return Ok(false);
+        }
+
+        let data = super::read_object(self.inner.as_ref(), from_run, path).await?;
+        if !data.starts_with(INDEX_MAGIC) {
+            return Ok(false);
+        }
+
+        self.lock_chunks().await?;
+        super::write_object(self.inner.as_ref(), to_run, path, &data).await?;
+        Ok(true)
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        self.inner.delete_object(run, path).await
+    }
+
+    /// The chunks of the run stay until `after_prune`.
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        self.inner.delete_run(run).await
+    }
+
+    async fn after_prune(&self) -> Result<()> {
+        self.collect_garbage().await
+    }
+}
+
+struct DedupWriter {
+    dedup: Dedup,
+    run: String,
+    path: String,
+    buffer: Vec<u8>,
+    chunks: Vec<ChunkRef>,
+    new: usize,
+}
+
+impl DedupWriter {
+    /// Stores the chunks of the buffer, the last one only with `last` since more data could move its end.
+    async fn store_chunks(&mut self, last: bool) -> Result<()> {
+        let cuts: Vec<(usize, usize)> = fastcdc::v2020::FastCDC::new(&self.buffer, MIN_CHUNK, AVG_CHUNK, MAX_CHUNK)
+            .map(|chunk| (chunk.offset, chunk.length))
+            .collect();
+
+        let keep = match (last, cuts.last()) {
+            (false, Some((offset, _))) => *offset,
+            _ => self.buffer.len(),
+        };
+
+        let rest = self.buffer.split_off(keep);
+        let data = std::mem::replace(&mut self.buffer, rest);
+
+        for (offset, length) in cuts.into_iter().take_while(|(offset, _)| *offset < keep) {
+            let (chunk, new) = self.dedup.store(&data[offset..offset + length]).await?;
+            self.new += new as usize;
+            self.chunks.push(chunk);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ObjectWriter for DedupWriter {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.buffer.extend_from_slice(buf);
+
+        if self.buffer.len() >= 4 * MAX_CHUNK as usize {
+            self.store_chunks(false).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        self.store_chunks(true).await?;
+
+        crate::logger::debug_string(format!(
+            "\"{}/{}\": stored {} of {} chunks", self.run, self.path, self.new, self.chunks.len()
+        ));
+
+        let mut data = INDEX_MAGIC.to_vec();
+        serde_json::to_writer(&mut data, &Index { chunks: self.chunks })?;
+        super::write_object(self.dedup.inner.as_ref(), &self.run, &self.path, &data).await
+    }
+}
+
+struct DedupReader {
+    inner: Arc<dyn BackupSink>,
+    chunks: VecDeque<ChunkRef>,
+}
+
+#[async_trait]
+impl ObjectReader for DedupReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let chunk = match self.chunks.pop_front() {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+
+        let stored = super::read_object(self.inner.as_ref(), CHUNKS, &chunk_path(&chunk.id)).await?;
+
+        let data = match stored.split_first() {
+            Some((&TAG_RAW, data)) => data.to_vec(),
+            Some((&TAG_GZIP, data)) => {
+                let mut decoded = Vec::with_capacity(chunk.size);
+                GzDecoder::new(data).read_to_end(&mut decoded)?;
+                decoded
+            }
+            _ => return Err(Error::Corrupt(format!("Chunk {} has an unknown format", chunk.id))),
+        };
+
+        if data.len() != chunk.size || hex::encode(Sha256::digest(&data)) != chunk.id {
+            return Err(Error::Corrupt(format!("Chunk {} does not match its checksum", chunk.id)));
+        }
+
+        Ok(Some(data))
+    }
+}
+
+struct PlainReader {
+    head: Option<Vec<u8>>,
+    reader: Box<dyn ObjectReader>,
+}
+
+#[async_trait]
+impl ObjectReader for PlainReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.head.take() {
+            Some(head) if !head.is_empty() => Ok(Some(head)),
+            _ => self.reader.read_chunk().await,
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn is_passthrough(path: &str) -> bool {
+    path == MANIFEST_FILE || path == SIGNATURE_FILE
+}
+
+fn chunk_path(id: &str) -> String {
+    format!("{}/{id}", &id[..2])
+}
+
+/// `None` when `data` is not an index.
+fn parse_index(data: &[u8]) -> Result<Option<Index>> {
+    match data.strip_prefix(INDEX_MAGIC) {
+        Some(json) => Ok(Some(serde_json::from_slice(json)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::{read_object, write_object, Memory};
+
+    /// Bytes that do not repeat, so that they are cut into several chunks.
+    fn sample(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    async fn write_run(sink: &Dedup, run: &str, data: &[u8]) {
+        write_object(sink, run, "db/collection.bson", data).await.unwrap();
+        write_object(sink, run, MANIFEST_FILE, b"{}").await.unwrap();
+        sink.finalize(run).await.unwrap();
+    }
+
+    async fn chunk_count(inner: &Memory) -> usize {
+        inner.list_objects(CHUNKS).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let inner = Memory::new();
+        let sink = Dedup::new(Arc::new(inner.clone()), Compression::Gzip);
+        let data = sample(3 * MAX_CHUNK as usize, 1);
+
+        write_run(&sink, "2024.01.01 00-00", &data).await;
+        let chunks = chunk_count(&inner).await;
+        assert!(chunks > 1);
+
+        write_run(&sink, "2024.01.02 00-00", &data).await;
+        assert_eq!(chunk_count(&inner).await, chunks);
+
+        assert_eq!(read_object(&sink, "2024.01.02 00-00", "db/collection.bson").await.unwrap(), data);
+        assert_eq!(read_object(&inner, "2024.01.02 00-00", MANIFEST_FILE).await.unwrap(), b"{}");
+        assert!(read_object(&inner, "2024.01.02 00-00", "db/collection.bson").await.unwrap().starts_with(INDEX_MAGIC));
+
+        // The lock is released with the run
+        assert_eq!(inner.list_runs().await.unwrap(), vec!["2024.01.01 00-00", "2024.01.02 00-00", CHUNKS]);
+        assert_eq!(sink.list_runs().await.unwrap(), vec!["2024.01.01 00-00", "2024.01.02 00-00"]);
+    }
+
+    #[tokio::test]
+    async fn plain_objects_are_read_as_they_are() {
+        let inner = Memory::new();
+        write_object(&inner, "2024.01.01 00-00", "old.bson", b"written before dedup").await.unwrap();
+
+        let sink = Dedup::new(Arc::new(inner), Compression::None);
+        assert_eq!(read_object(&sink, "2024.01.01 00-00", "old.bson").await.unwrap(), b"written before dedup");
+    }
+
+    #[tokio::test]
+    async fn garbage_collection() {
+        let inner = Memory::new();
+        let sink = Dedup::new(Arc::new(inner.clone()), Compression::None);
+        let kept = sample(2 * MAX_CHUNK as usize, 2);
+
+        write_run(&sink, "2024.01.01 00-00", &sample(2 * MAX_CHUNK as usize, 3)).await;
+        let first = chunk_count(&inner).await;
+        write_run(&sink, "2024.01.02 00-00", &kept).await;
+        let both = chunk_count(&inner).await;
+
+        sink.delete_run("2024.01.01 00-00").await.unwrap();
+        assert_eq!(chunk_count(&inner).await, both);
+
+        sink.after_prune().await.unwrap();
+        assert_eq!(chunk_count(&inner).await, both - first);
+        assert_eq!(read_object(&sink, "2024.01.02 00-00", "db/collection.bson").await.unwrap(), kept);
+        assert!(!inner.list_runs().await.unwrap().iter().any(|run| run == LOCK));
+    }
+
+    #[tokio::test]
+    async fn chunks_removed_by_another_sink_are_written_again() {
+        let inner = Memory::new();
+        let daemon = Dedup::new(Arc::new(inner.clone()), Compression::None);
+        let prune = Dedup::new(Arc::new(inner.clone()), Compression::None);
+        let data = sample(2 * MAX_CHUNK as usize, 4);
+
+        write_run(&daemon, "2024.01.01 00-00", &data).await;
+
+        prune.delete_run("2024.01.01 00-00").await.unwrap();
+        prune.after_prune().await.unwrap();
+        assert_eq!(chunk_count(&inner).await, 0);
+
+        write_run(&daemon, "2024.01.02 00-00", &data).await;
+        assert_eq!(read_object(&daemon, "2024.01.02 00-00", "db/collection.bson").await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn garbage_collection_waits_for_a_running_backup() {
+        let inner = Memory::new();
+        let daemon = Dedup::new(Arc::new(inner.clone()), Compression::None);
+        let prune = Dedup::new(Arc::new(inner.clone()), Compression::None);
+        let data = sample(5 * MAX_CHUNK as usize, 5);
+
+        write_run(&daemon, "2024.01.01 00-00", &sample(MAX_CHUNK as usize, 6)).await;
+        let old = chunk_count(&inner).await;
+
+        // Chunks are stored before the index of the object is written
+        let mut writer = daemon.create_object("2024.01.02 00-00", "db/collection.bson").await.unwrap();
+        writer.write(&data).await.unwrap();
+        let stored = chunk_count(&inner).await;
+        assert!(stored > old);
+
+        prune.delete_run("2024.01.01 00-00").await.unwrap();
+        prune.after_prune().await.unwrap();
+        assert_eq!(chunk_count(&inner).await, stored);
+
+        writer.finish().await.unwrap();
+        daemon.finalize("2024.01.02 00-00").await.unwrap();
+        assert_eq!(read_object(&daemon, "2024.01.02 00-00", "db/collection.bson").await.unwrap(), data);
+        let all = chunk_count(&inner).await;
+
+        prune.after_prune().await.unwrap();
+        assert_eq!(chunk_count(&inner).await, all - old);
+    }
+
+    #[tokio::test]
+    async fn expired_lock_is_taken_over() {
+        let inner = Memory::new();
+        let lock = Lock { owner: "ended process".to_string(), renewed: unix_now() - LOCK_TIMEOUT };
+        write_object(&inner, LOCK, LOCK_FILE, &serde_json::to_vec(&lock).unwrap()).await.unwrap();
+
+        let sink = Dedup::new(Arc::new(inner.clone()), Compression::None);
+        write_run(&sink, "2024.01.01 00-00", b"data").await;
+
+        assert_eq!(read_object(&sink, "2024.01.01 00-00", "db/collection.bson").await.unwrap(), b"data");
+        assert!(!inner.list_runs().await.unwrap().iter().any(|run| run == LOCK));
+    }
+}
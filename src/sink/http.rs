@@ -0,0 +1,79 @@
+use quick_xml::events::Event;
+use reqwest::{Response, StatusCode};
+
+use crate::{Error, Result};
+
+/// Turns a non-2xx response into an error with the server message.
+pub async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let url = response.url().path().to_string();
+    let body = response.text().await.unwrap_or_default();
+    let message = xml_value(&body, "Message").ok().flatten()
+        .or_else(|| xml_value(&body, "message").ok().flatten())
+        .unwrap_or(body);
+
+    match status {
+        StatusCode::NOT_FOUND => Err(Error::Storage(format!("{url} not found"))),
+        _ => Err(Error::Storage(format!("{url}: {status} {message}"))),
+    }
+}
+
+/// Percent-encodes everything but unreserved characters (the SigV4 rules), `/` only when `slash` is set.
+pub fn uri_encode(value: &str, slash: bool) -> String {
+    let mut encoded = String::new();
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+pub fn uri_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// First text value of `name` in an XML document.
+pub fn xml_value(body: &str, name: &str) -> Result<Option<String>> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    let mut inside = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => inside = start.local_name().as_ref() == name.as_bytes(),
+            Ok(Event::End(_)) => inside = false,
+            Ok(Event::Text(text)) if inside => {
+                return Ok(Some(text.unescape().map_err(|err| Error::Storage(err.to_string()))?.to_string()));
+            }
+            Ok(Event::Eof) => return Ok(None),
+            Err(err) => return Err(Error::Storage(format!("Invalid XML response: {err}"))),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use std::{fs::{self, File}, io::{BufWriter, Read, Write}, path::{Path, PathBuf}};
+
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::Result;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Stores every run as a directory under `root`.
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+impl LocalFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalFs { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn run_path(&self, run: &str) -> PathBuf {
+        self.root.join(run)
+    }
+}
+
+#[async_trait]
+impl BackupSink for LocalFs {
+    fn describe(&self) -> String {
+        self.root.to_str().unwrap_or_default().to_string()
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        let full_path = super::object_path(&self.run_path(run), path)?;
+
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut part_path = full_path.clone().into_os_string();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+
+        let file = File::create(&part_path)?;
+
+        Ok(Box::new(LocalFile { file: BufWriter::new(file), part_path, full_path }))
+    }
+
+    async fn finalize(&self, _run: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut runs = Vec::new();
+
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+
+            if entry.file_type()?.is_dir() {
+                runs.push(entry.file_name().into_string().unwrap_or_default());
+            }
+        }
+
+        runs.sort();
+        Ok(runs)
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        let mut objects = Vec::new();
+        collect_files(&self.run_path(run), "", &mut objects)?;
+        objects.sort();
+        Ok(objects)
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let file = File::open(super::object_path(&self.run_path(run), path)?)?;
+        Ok(Box::new(LocalReader { file }))
+    }
+
+    async fn link_object(&self, from_run: &str, to_run: &str, path: &str) -> Result<bool> {
+        let from_path = super::object_path(&self.run_path(from_run), path)?;
+        let to_path = super::object_path(&self.run_path(to_run), path)?;
+
+        if !from_path.is_file() {
+            return Ok(false);
+        }
+
+        if let Some(parent) = to_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if to_path.exists() {
+            fs::remove_file(&to_path)?;
+        }
+
+        fs::hard_link(from_path, to_path)?;
+        Ok(true)
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        let path = super::object_path(&self.run_path(run), path)?;
+
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        let path = self.run_path(run);
+
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn collect_files(dir: &Path, prefix: &str, objects: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().unwrap_or_default();
+        let path = format!("{prefix}{name}");
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{path}/"), objects)?;
+        } else if !name.ends_with(".part") {
+            objects.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+struct LocalFile {
+    file: BufWriter<File>,
+    part_path: PathBuf,
+    full_path: PathBuf,
+}
+
+#[async_trait]
+impl ObjectWriter for LocalFile {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.file.write_all(buf)?;
+        Ok(())
+    }
+
+    async fn finish(self: Box<Self>) -> Result<()> {
+        let file = self.file.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        fs::rename(&self.part_path, &self.full_path)?;
+        Ok(())
+    }
+}
+
+struct LocalReader {
+    file: File,
+}
+
+#[async_trait]
+impl ObjectReader for LocalReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let read = self.file.read(&mut chunk)?;
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        chunk.truncate(read);
+        Ok(Some(chunk))
+    }
+}
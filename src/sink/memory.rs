@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use std::{collections::BTreeMap, sync::{Arc, Mutex}};
+
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::{Error, Result};
+
+type Runs = BTreeMap<String, BTreeMap<String, Vec<u8>>>;
+
+/// Keeps runs in memory, mostly useful for tests of code built on the library.
+#[derive(Clone, Default)]
+pub struct Memory {
+    runs: Arc<Mutex<Runs>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+}
+
+#[async_trait]
+impl BackupSink for Memory {
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        Ok(Box::new(MemoryObject {
+            runs: self.runs.clone(),
+            run: run.to_string(),
+            path: path.to_string(),
+            data: Vec::new(),
+        }))
+    }
+
+    async fn finalize(&self, _run: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        Ok(lock(&self.runs)?.keys().cloned().collect())
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        Ok(lock(&self.runs)?.get(run).map(|objects| objects.keys().cloned().collect()).unwrap_or_default())
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let data = lock(&self.runs)?
+            .get(run)
+            .and_then(|objects| objects.get(path))
+            .cloned()
+            .ok_or_else(|| Error::Storage(format!("Object \"{run}/{path}\" not found")))?;
+
+        Ok(Box::new(MemoryReader { data: Some(data) }))
+    }
+
+    async fn link_object(&self, from_run: &str, to_run: &str, path: &str) -> Result<bool> {
+        let mut runs = lock(&self.runs)?;
+
+        let data = match runs.get(from_run).and_then(|objects| objects.get(path)) {
+            Some(res) => res.clone(),
+            None => return Ok(false),
+        };
+
+        runs.entry(to_run.to_string()).or_default().insert(path.to_string(), data);
+        Ok(true)
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        if let Some(objects) = lock(&self.runs)?.get_mut(run) {
+            objects.remove(path);
+        }
+        Ok(())
+    }
+
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        lock(&self.runs)?.remove(run);
+        Ok(())
+    }
+}
+
+fn lock(runs: &Mutex<Runs>) -> Result<std::sync::MutexGuard<'_, Runs>> {
+    runs.lock().map_err(|_| Error::Storage("Memory sink is poisoned".to_string()))
+}
+
+struct MemoryObject {
+    runs: Arc<Mutex<Runs>>,
+    run: String,
+    path: String,
+    data: Vec<u8>,
+}
+
+#[async_trait]
+impl ObjectWriter for MemoryObject {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.data.extend_from_slice(buf);
+        Ok(())
+    }
+
+    async fn finish(self: Box<Self>) -> Result<()> {
+        lock(&self.runs)?.entry(self.run).or_default().insert(self.path, self.data);
+        Ok(())
+    }
+}
+
+struct MemoryReader {
+    data: Option<Vec<u8>>,
+}
+
+#[async_trait]
+impl ObjectReader for MemoryReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.data.take())
+    }
+}
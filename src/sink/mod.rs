@@ -0,0 +1,184 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use crate::{Error, Result};
+
+mod azure;
+mod dedup;
+mod http;
+mod local;
+mod memory;
+mod s3;
+mod sftp;
+mod webdav;
+
+pub use azure::{Azure, AzureConfig};
+pub use dedup::{Dedup, CHUNKS, LOCK};
+pub use local::LocalFs;
+pub use memory::Memory;
+pub use s3::{S3, S3Config};
+pub use sftp::{Sftp, SftpConfig};
+pub use webdav::{WebDav, WebDavConfig};
+
+/// `storage` section of a connection in the config.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StorageConfig {
+    Local {
+        path: Option<String>,
+    },
+    S3(S3Config),
+    Sftp(SftpConfig),
+    WebDav(WebDavConfig),
+    Azure(AzureConfig),
+}
+
+impl StorageConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StorageConfig::Local { .. } => "local",
+            StorageConfig::S3(_) => "s3",
+            StorageConfig::Sftp(_) => "sftp",
+            StorageConfig::WebDav(_) => "webdav",
+            StorageConfig::Azure(_) => "azure",
+        }
+    }
+
+    /// Passwords, keys and tokens of the storage.
+    fn secrets(&self) -> Vec<&str> {
+        match self {
+            StorageConfig::Local { .. } => Vec::new(),
+            StorageConfig::S3(config) => vec![config.secret_key.as_str()],
+            StorageConfig::Sftp(config) => config.password.iter().chain(&config.passphrase).map(String::as_str).collect(),
+            StorageConfig::WebDav(config) => config.password.iter().chain(&config.token).map(String::as_str).collect(),
+            StorageConfig::Azure(config) => config.account_key.iter().chain(&config.sas_token).map(String::as_str).collect(),
+        }
+    }
+
+    /// Creates the sink that stores the runs of the connection `name`.
+    pub fn open(&self, name: &str) -> Result<Arc<dyn BackupSink>> {
+        for secret in self.secrets() {
+            crate::logger::add_secret(secret);
+        }
+
+        match self {
+            StorageConfig::Local { path } => {
+                let root = match path {
+                    Some(path) => Path::new(path).join(name),
+                    None => crate::paths::output().join(name),
+                };
+                Ok(Arc::new(LocalFs::new(root)))
+            }
+            StorageConfig::S3(config) => Ok(Arc::new(S3::new(config.clone(), name)?)),
+            StorageConfig::Sftp(config) => Ok(Arc::new(Sftp::new(config.clone(), name)?)),
+            StorageConfig::WebDav(config) => Ok(Arc::new(WebDav::new(config.clone(), name)?)),
+            StorageConfig::Azure(config) => Ok(Arc::new(Azure::new(config.clone(), name)?)),
+        }
+    }
+}
+
+/// Storage for backup runs. A run is a named group of objects (the dumped files
+/// and the manifest), object paths are relative to the run and use `/` as separator.
+#[async_trait]
+pub trait BackupSink: Send + Sync {
+    /// Location of the storage for logs.
+    fn describe(&self) -> String;
+
+    /// Creates (or replaces) an object; it becomes visible after `ObjectWriter::finish`.
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>>;
+
+    /// Called once every object of a run, including the manifest, has been written.
+    async fn finalize(&self, run: &str) -> Result<()>;
+
+    async fn list_runs(&self) -> Result<Vec<String>>;
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>>;
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>>;
+
+    /// Makes `path` of `from_run` also available in `to_run` without copying its data, like
+    /// a hard link. Returns `false` when the storage can not do that or the object is missing.
+    async fn link_object(&self, _from_run: &str, _to_run: &str, _path: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Removes a single object, a missing object is not an error.
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()>;
+
+    async fn delete_run(&self, run: &str) -> Result<()>;
+
+    /// Called once after runs were deleted, to clean up what they shared (see `Dedup`).
+    async fn after_prune(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait ObjectWriter: Send {
+    async fn write(&mut self, buf: &[u8]) -> Result<()>;
+
+    async fn finish(self: Box<Self>) -> Result<()>;
+}
+
+#[async_trait]
+pub trait ObjectReader: Send {
+    /// Returns `None` at the end of the object.
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>>;
+}
+
+pub async fn read_object(sink: &dyn BackupSink, run: &str, path: &str) -> Result<Vec<u8>> {
+    let mut reader = sink.open_object(run, path).await?;
+    let mut data = Vec::new();
+
+    while let Some(chunk) = reader.read_chunk().await? {
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(data)
+}
+
+pub async fn write_object(sink: &dyn BackupSink, run: &str, path: &str, data: &[u8]) -> Result<()> {
+    let mut writer = sink.create_object(run, path).await?;
+    writer.write(data).await?;
+    writer.finish().await
+}
+
+/// Copies every object of `from_run` to `to_run` of `to`, the manifest last, and finalizes the copy.
+pub async fn copy_run(from: &dyn BackupSink, from_run: &str, to: &dyn BackupSink, to_run: &str) -> Result<()> {
+    let mut objects = from.list_objects(from_run).await?;
+    objects.sort_by_key(|object| object == crate::report::MANIFEST_FILE);
+
+    for object in &objects {
+        let mut reader = from.open_object(from_run, object).await?;
+        let mut writer = to.create_object(to_run, object).await?;
+
+        while let Some(chunk) = reader.read_chunk().await? {
+            writer.write(&chunk).await?;
+        }
+
+        writer.finish().await?;
+    }
+
+    to.finalize(to_run).await?;
+
+    let copied = to.list_objects(to_run).await?;
+    match objects.iter().all(|object| copied.contains(object)) {
+        true => Ok(()),
+        false => Err(Error::Storage(format!("Copy of \"{from_run}\" is incomplete"))),
+    }
+}
+
+/// Joins a `/` separated object path to `run_path`, refusing paths that leave the run.
+pub(crate) fn object_path(run_path: &Path, path: &str) -> Result<PathBuf> {
+    let mut full_path = run_path.to_path_buf();
+
+    for part in path.split('/') {
+        if part.is_empty() || part == "." || part == ".." {
+            return Err(Error::Storage(format!("Invalid object path \"{path}\"")));
+        }
+        full_path.push(part);
+    }
+
+    Ok(full_path)
+}
@@ -0,0 +1,432 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use quick_xml::events::Event;
+use reqwest::{Method, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use super::http::{check_status, uri_encode, xml_value};
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::{Error, Result};
+
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct S3Config {
+    /// `http(s)://host:port` of the S3 API, e.g. `http://localhost:9000` for MinIO.
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(rename = "accessKey")]
+    pub access_key: String,
+    #[serde(rename = "secretKey")]
+    pub secret_key: String,
+    /// Size of a multipart upload part in MiB.
+    #[serde(default = "default_part_size", rename = "partSize")]
+    pub part_size: usize,
+    /// Use `endpoint/bucket/key` addressing (MinIO) instead of `bucket.endpoint/key`.
+    #[serde(default = "default_path_style", rename = "pathStyle")]
+    pub path_style: bool,
+    /// Storage class of uploaded objects, e.g. `STANDARD_IA` or `GLACIER` for a cold tier.
+    #[serde(default, rename = "storageClass", skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_part_size() -> usize {
+    8
+}
+
+fn default_path_style() -> bool {
+    true
+}
+
+/// Stores runs as objects `<prefix>/<name>/<run>/<path>` of a bucket.
+#[derive(Clone)]
+pub struct S3 {
+    config: S3Config,
+    prefix: String,
+    client: reqwest::Client,
+}
+
+impl S3 {
+    pub fn new(config: S3Config, name: &str) -> Result<Self> {
+        if config.bucket.is_empty() {
+            return Err(Error::Config(format!("S3 bucket of \"{name}\" can not be empty")));
+        }
+
+        let prefix = match config.prefix.trim_matches('/') {
+            "" => format!("{name}/"),
+            prefix => format!("{prefix}/{name}/"),
+        };
+
+        Ok(S3 { config, prefix, client: reqwest::Client::new() })
+    }
+
+    fn key(&self, run: &str, path: &str) -> String {
+        format!("{}{run}/{path}", self.prefix)
+    }
+
+    fn url(&self, key: &str) -> (String, String) {
+        let endpoint = self.config.endpoint.trim_end_matches('/');
+        let (scheme, host) = endpoint.split_once("://").unwrap_or(("https", endpoint));
+
+        match self.config.path_style {
+            true => (format!("{scheme}://{host}"), format!("/{}/{}", self.config.bucket, uri_encode(key, false))),
+            false => (format!("{scheme}://{}.{host}", self.config.bucket), format!("/{}", uri_encode(key, false))),
+        }
+    }
+
+    async fn request(&self, method: Method, key: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<Response> {
+        self.request_with_headers(method, key, query, &[], body).await
+    }
+
+    /// Signed request; `headers` must be lowercase `x-amz-*` headers, they are signed as well.
+    async fn request_with_headers(&self, method: Method, key: &str, query: &[(&str, &str)], headers: &[(&str, &str)], body: Vec<u8>) -> Result<Response> {
+        let (base, path) = self.url(key);
+        let host = base.split_once("://").map(|(_, host)| host).unwrap_or(&base).to_string();
+
+        let mut query: Vec<(String, String)> = query.iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        query.sort();
+        let query = query.iter().map(|(name, value)| format!("{name}={value}")).collect::<Vec<_>>().join("&");
+
+        let now = OffsetDateTime::now_utc();
+        let date = format!("{:04}{:02}{:02}", now.year(), now.month() as u8, now.day());
+        let amz_date = format!("{date}T{:02}{:02}{:02}Z", now.hour(), now.minute(), now.second());
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let mut signed: Vec<(&str, &str)> = vec![("host", &host), ("x-amz-content-sha256", &payload_hash), ("x-amz-date", &amz_date)];
+        signed.extend_from_slice(headers);
+        signed.sort();
+        let canonical_headers: String = signed.iter().map(|(name, value)| format!("{name}:{value}\n")).collect();
+        let signed_headers = signed.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.config.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.config.access_key
+        );
+
+        let url = match query.is_empty() {
+            true => format!("{base}{path}"),
+            false => format!("{base}{path}?{query}"),
+        };
+
+        let mut request = self.client.request(method, url)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header("authorization", authorization);
+
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = request.body(body).send().await?;
+
+        check_status(response).await
+    }
+
+    fn upload_headers(&self) -> Vec<(&str, &str)> {
+        match &self.config.storage_class {
+            Some(class) => vec![("x-amz-storage-class", class.as_str())],
+            None => Vec::new(),
+        }
+    }
+
+    /// Lists keys under `prefix`; with `delimiter` only the next level of "directories" is returned.
+    async fn list(&self, prefix: &str, delimiter: bool) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if delimiter {
+                query.push(("delimiter", "/"));
+            }
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+
+            let body = self.request(Method::GET, "", &query, Vec::new()).await?.text().await?;
+            let listing = parse_list(&body)?;
+
+            match delimiter {
+                true => keys.extend(listing.prefixes),
+                false => keys.extend(listing.keys),
+            }
+
+            match listing.next_token {
+                Some(next) => token = Some(next),
+                None => return Ok(keys),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BackupSink for S3 {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.config.bucket, self.prefix)
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        Ok(Box::new(S3Object {
+            sink: self.clone(),
+            key: self.key(run, path),
+            part_size: (self.config.part_size * 1024 * 1024).max(MIN_PART_SIZE),
+            buffer: Vec::new(),
+            upload_id: None,
+            parts: Vec::new(),
+        }))
+    }
+
+    async fn finalize(&self, _run: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        let mut runs: Vec<String> = self.list(&self.prefix, true).await?
+            .iter()
+            .filter_map(|prefix| prefix.strip_prefix(&self.prefix))
+            .map(|run| run.trim_end_matches('/').to_string())
+            .collect();
+        runs.sort();
+        Ok(runs)
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        let run_prefix = format!("{}{run}/", self.prefix);
+        let mut objects: Vec<String> = self.list(&run_prefix, false).await?
+            .iter()
+            .filter_map(|key| key.strip_prefix(&run_prefix))
+            .map(|path| path.to_string())
+            .collect();
+        objects.sort();
+        Ok(objects)
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let response = self.request(Method::GET, &self.key(run, path), &[], Vec::new()).await?;
+        Ok(Box::new(S3Reader { response }))
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        self.request(Method::DELETE, &self.key(run, path), &[], Vec::new()).await?;
+        Ok(())
+    }
+
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        for key in self.list(&format!("{}{run}/", self.prefix), false).await? {
+            self.request(Method::DELETE, &key, &[], Vec::new()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Uploads with a single PUT when the object fits into one part, with a multipart upload otherwise.
+struct S3Object {
+    sink: S3,
+    key: String,
+    part_size: usize,
+    buffer: Vec<u8>,
+    upload_id: Option<String>,
+    parts: Vec<String>,
+}
+
+impl S3Object {
+    async fn upload_part(&mut self) -> Result<()> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let body = self.sink.request_with_headers(Method::POST, &self.key, &[("uploads", "")], &self.sink.upload_headers(), Vec::new())
+                    .await?
+                    .text()
+                    .await?;
+                let upload_id = xml_value(&body, "UploadId")?
+                    .ok_or_else(|| Error::Storage(format!("No UploadId for \"{}\"", self.key)))?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            }
+        };
+
+        let part_number = (self.parts.len() + 1).to_string();
+        let body = std::mem::take(&mut self.buffer);
+        let query = [("partNumber", part_number.as_str()), ("uploadId", upload_id.as_str())];
+
+        let response = match self.sink.request(Method::PUT, &self.key, &query, body).await {
+            Ok(res) => res,
+            Err(err) => {
+                self.abort().await;
+                return Err(err);
+            }
+        };
+
+        // Completing the upload with a wrong ETag would fail or store a different object
+        let etag = match response.headers().get("etag").and_then(|etag| etag.to_str().ok()) {
+            Some(res) => res.to_string(),
+            None => {
+                self.abort().await;
+                return Err(Error::Storage(format!("No ETag for part {part_number} of \"{}\"", self.key)));
+            }
+        };
+        self.parts.push(etag);
+
+        Ok(())
+    }
+
+    async fn abort(&mut self) {
+        if let Some(upload_id) = self.upload_id.take() {
+            abort_upload(&self.sink, &self.key, &upload_id).await;
+        }
+    }
+}
+
+/// An upload that is neither completed nor aborted keeps its parts stored (and billed).
+impl Drop for S3Object {
+    fn drop(&mut self) {
+        let upload_id = match self.upload_id.take() {
+            Some(res) => res,
+            None => return,
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let sink = self.sink.clone();
+                let key = std::mem::take(&mut self.key);
+                runtime.spawn(async move { abort_upload(&sink, &key, &upload_id).await });
+            }
+            Err(_) => crate::logger::warn_string(format!("Upload \"{upload_id}\" of \"{}\" has not been aborted", self.key)),
+        }
+    }
+}
+
+async fn abort_upload(sink: &S3, key: &str, upload_id: &str) {
+    if let Err(err) = sink.request(Method::DELETE, key, &[("uploadId", upload_id)], Vec::new()).await {
+        crate::logger::warn_string(format!("Failed to abort upload of \"{key}\" > {err}"));
+    }
+}
+
+#[async_trait]
+impl ObjectWriter for S3Object {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.buffer.extend_from_slice(buf);
+
+        if self.buffer.len() >= self.part_size {
+            self.upload_part().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.upload_id.is_none() {
+            let body = std::mem::take(&mut self.buffer);
+            self.sink.request_with_headers(Method::PUT, &self.key, &[], &self.sink.upload_headers(), body).await?;
+            return Ok(());
+        }
+
+        if !self.buffer.is_empty() {
+            self.upload_part().await?;
+        }
+
+        let upload_id = self.upload_id.clone().unwrap_or_default();
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in self.parts.iter().enumerate() {
+            body.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>", index + 1));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        if let Err(err) = self.sink.request(Method::POST, &self.key, &[("uploadId", &upload_id)], body.into_bytes()).await {
+            self.abort().await;
+            return Err(err);
+        }
+
+        // Completed, nothing to abort when dropped
+        self.upload_id = None;
+        Ok(())
+    }
+}
+
+struct S3Reader {
+    response: Response,
+}
+
+#[async_trait]
+impl ObjectReader for S3Reader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.response.chunk().await?.map(|chunk| chunk.to_vec()))
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[derive(Default)]
+struct Listing {
+    keys: Vec<String>,
+    prefixes: Vec<String>,
+    next_token: Option<String>,
+}
+
+fn parse_list(body: &str) -> Result<Listing> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    let mut listing = Listing::default();
+    let mut element = String::new();
+    let mut in_prefixes = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => {
+                element = String::from_utf8_lossy(start.local_name().as_ref()).to_string();
+                if element == "CommonPrefixes" {
+                    in_prefixes = true;
+                }
+            }
+            Ok(Event::End(end)) => {
+                if end.local_name().as_ref() == b"CommonPrefixes" {
+                    in_prefixes = false;
+                }
+                element.clear();
+            }
+            Ok(Event::Text(text)) => {
+                let value = text.unescape().map_err(|err| Error::Storage(err.to_string()))?.to_string();
+                match element.as_str() {
+                    "Key" => listing.keys.push(value),
+                    "Prefix" if in_prefixes => listing.prefixes.push(value),
+                    "NextContinuationToken" => listing.next_token = Some(value),
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => return Ok(listing),
+            Err(err) => return Err(Error::Storage(format!("Invalid S3 response: {err}"))),
+            _ => {}
+        }
+    }
+}
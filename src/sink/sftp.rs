@@ -0,0 +1,420 @@
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use ssh2::{CheckResult, ErrorCode, HashType, KnownHostFileKind, RenameFlags, Session};
+use std::{io::{Read, Write}, net::TcpStream, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::{Error, Result};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// `SSH_FX_OP_UNSUPPORTED` of the SFTP protocol.
+const SFTP_OP_UNSUPPORTED: i32 = 8;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SftpConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub user: String,
+    /// Private key for public key authentication.
+    #[serde(rename = "keyFile")]
+    pub key_file: Option<String>,
+    pub passphrase: Option<String>,
+    pub password: Option<String>,
+    /// Remote directory that receives `<name>/<run>/<file>`.
+    pub path: String,
+    /// Expected host key fingerprint as printed by `ssh-keygen -lf` (`SHA256:...`).
+    pub fingerprint: Option<String>,
+    /// OpenSSH known hosts file the host key is checked with when there is no `fingerprint`,
+    /// defaults to `~/.ssh/known_hosts`.
+    #[serde(rename = "knownHosts")]
+    pub known_hosts: Option<String>,
+}
+
+fn default_port() -> u16 {
+    22
+}
+
+/// Stores runs as directories on a remote host over SFTP. Remote paths always use `/`,
+/// whatever the separator of the local system is.
+pub struct Sftp {
+    config: SftpConfig,
+    root: String,
+    session: Arc<Mutex<Option<ssh2::Sftp>>>,
+}
+
+impl Sftp {
+    pub fn new(config: SftpConfig, name: &str) -> Result<Self> {
+        if config.host.is_empty() {
+            return Err(Error::Config(format!("SFTP host of \"{name}\" can not be empty")));
+        }
+
+        if config.key_file.is_none() && config.password.is_none() {
+            return Err(Error::Config(format!("SFTP of \"{name}\" needs \"keyFile\" or \"password\"")));
+        }
+
+        let root = match config.path.trim_end_matches('/') {
+            "" if config.path.starts_with('/') => format!("/{name}"),
+            "" => name.to_string(),
+            path => format!("{path}/{name}"),
+        };
+
+        Ok(Sftp { config, root, session: Arc::new(Mutex::new(None)) })
+    }
+
+    /// Runs `operation` on a blocking thread with a connected SFTP channel, retrying once on a
+    /// fresh session when the cached one is broken. SFTP errors like a missing file are returned as they are.
+    async fn with_sftp<T, F>(&self, operation: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: Fn(&ssh2::Sftp) -> Result<T> + Send + 'static,
+    {
+        let config = self.config.clone();
+        let session = self.session.clone();
+
+        blocking(move || {
+            let mut cached = session.lock().map_err(|_| Error::Storage("SFTP session is poisoned".to_string()))?;
+
+            if let Some(sftp) = cached.as_ref() {
+                match operation(sftp) {
+                    Err(err) if is_broken(&err) => {
+                        *cached = None;
+
+                        let sftp = match connect(&config) {
+                            Ok(res) => res,
+                            Err(reconnect) => return Err(Error::Storage(format!("{err}, reconnecting failed: {reconnect}"))),
+                        };
+
+                        let result = operation(&sftp);
+                        *cached = Some(sftp);
+                        return result;
+                    }
+                    result => return result,
+                }
+            }
+
+            let sftp = connect(&config)?;
+            let result = operation(&sftp);
+            *cached = Some(sftp);
+            result
+        }).await
+    }
+
+    fn run_path(&self, run: &str) -> String {
+        format!("{}/{run}", self.root)
+    }
+}
+
+#[async_trait]
+impl BackupSink for Sftp {
+    fn describe(&self) -> String {
+        format!("sftp://{}@{}:{}/{}", self.config.user, self.config.host, self.config.port, self.root.trim_start_matches('/'))
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        let full_path = remote_path(&self.run_path(run), path)?;
+        let part_path = format!("{full_path}.part");
+
+        let open_path = part_path.clone();
+        let file = self.with_sftp(move |sftp| {
+            if let Some((parent, _)) = open_path.rsplit_once('/').filter(|(parent, _)| !parent.is_empty()) {
+                create_dir_all(sftp, parent)?;
+            }
+            Ok(sftp.create(Path::new(&open_path))?)
+        }).await?;
+
+        Ok(Box::new(SftpFile { file: Some(file), part_path, full_path, session: self.session.clone() }))
+    }
+
+    async fn finalize(&self, _run: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        let root = self.root.clone();
+
+        self.with_sftp(move |sftp| {
+            if sftp.stat(Path::new(&root)).is_err() {
+                return Ok(Vec::new());
+            }
+
+            let mut runs: Vec<String> = sftp.readdir(Path::new(&root))?
+                .into_iter()
+                .filter(|(_, stat)| stat.is_dir())
+                .filter_map(|(path, _)| path.file_name().and_then(|name| name.to_str()).map(|name| name.to_string()))
+                .collect();
+            runs.sort();
+            Ok(runs)
+        }).await
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        let run_path = self.run_path(run);
+
+        self.with_sftp(move |sftp| {
+            let mut objects = Vec::new();
+            collect_files(sftp, &run_path, "", &mut objects)?;
+            objects.sort();
+            Ok(objects)
+        }).await
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let full_path = remote_path(&self.run_path(run), path)?;
+        let file = self.with_sftp(move |sftp| Ok(sftp.open(Path::new(&full_path))?)).await?;
+
+        Ok(Box::new(SftpReader { file: Some(file) }))
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        let full_path = remote_path(&self.run_path(run), path)?;
+
+        self.with_sftp(move |sftp| {
+            if sftp.stat(Path::new(&full_path)).is_ok() {
+                sftp.unlink(Path::new(&full_path))?;
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        let run_path = self.run_path(run);
+
+        self.with_sftp(move |sftp| {
+            if sftp.stat(Path::new(&run_path)).is_ok() {
+                remove_dir_all(sftp, &run_path)?;
+            }
+            Ok(())
+        }).await
+    }
+}
+
+fn connect(config: &SftpConfig) -> Result<ssh2::Sftp> {
+    let tcp = TcpStream::connect((config.host.as_str(), config.port))?;
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.handshake()?;
+
+    verify_host_key(&session, config)?;
+
+    match &config.key_file {
+        Some(key_file) => session.userauth_pubkey_file(&config.user, None, Path::new(key_file), config.passphrase.as_deref())?,
+        None => session.userauth_password(&config.user, config.password.as_deref().unwrap_or_default())?,
+    }
+
+    if !session.authenticated() {
+        return Err(Error::Storage(format!("SFTP authentication of {}@{} failed", config.user, config.host)));
+    }
+
+    Ok(session.sftp()?)
+}
+
+/// Checks the host key with `fingerprint`, or with the known hosts file without it. Unknown keys
+/// are refused, the password and the backups must not go to someone in the middle.
+fn verify_host_key(session: &Session, config: &SftpConfig) -> Result<()> {
+    let fingerprint = session.host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash)))
+        .unwrap_or_default();
+
+    if let Some(expected) = &config.fingerprint {
+        return match *expected == fingerprint {
+            true => Ok(()),
+            false => Err(Error::Storage(format!("Host key of {} is {fingerprint}, expected {expected}", config.host))),
+        };
+    }
+
+    let path = match (&config.known_hosts, std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(home)) => PathBuf::from(home).join(".ssh").join("known_hosts"),
+        (None, None) => return Err(Error::Config(format!("Host key of {} can not be checked without a home directory, set \"fingerprint\" or \"knownHosts\"", config.host))),
+    };
+
+    let (key, _) = session.host_key().ok_or_else(|| Error::Storage(format!("{} sent no host key", config.host)))?;
+
+    let mut known_hosts = session.known_hosts()?;
+    if let Err(err) = known_hosts.read_file(&path, KnownHostFileKind::OpenSSH) {
+        return Err(Error::Storage(format!("Failed to read known hosts \"{}\": {err}", path.display())));
+    }
+
+    match known_hosts.check_port(&config.host, config.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(Error::Storage(format!("Host key of {} ({fingerprint}) does not match \"{}\"", config.host, path.display()))),
+        CheckResult::NotFound | CheckResult::Failure => Err(Error::Storage(format!(
+            "Host key of {} ({fingerprint}) is not in \"{}\", add it with ssh-keyscan or set \"fingerprint\"", config.host, path.display()
+        ))),
+    }
+}
+
+/// Whether `err` comes from the session or its connection rather than from the SFTP server.
+fn is_broken(err: &Error) -> bool {
+    match err {
+        Error::Ssh(err) => matches!(err.code(), ErrorCode::Session(_)),
+        _ => false,
+    }
+}
+
+async fn blocking<T, F>(operation: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(operation)
+        .await
+        .map_err(|err| Error::Storage(format!("SFTP task failed: {err}")))?
+}
+
+/// `path` of an object (`/` separated) below the remote directory `run_path`.
+fn remote_path(run_path: &str, path: &str) -> Result<String> {
+    if path.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+        return Err(Error::Storage(format!("Invalid object path \"{path}\"")));
+    }
+
+    Ok(format!("{run_path}/{path}"))
+}
+
+/// Names of the entries of the remote directory `dir`.
+fn read_dir(sftp: &ssh2::Sftp, dir: &str) -> Result<Vec<(String, ssh2::FileStat)>> {
+    Ok(sftp.readdir(Path::new(dir))?
+        .into_iter()
+        .filter_map(|(path, stat)| path.file_name().and_then(|name| name.to_str()).map(|name| (name.to_string(), stat)))
+        .collect())
+}
+
+fn create_dir_all(sftp: &ssh2::Sftp, path: &str) -> Result<()> {
+    let mut current = match path.starts_with('/') {
+        true => "/".to_string(),
+        false => String::new(),
+    };
+
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        if !current.is_empty() && !current.ends_with('/') {
+            current.push('/');
+        }
+        current.push_str(part);
+
+        if sftp.stat(Path::new(&current)).is_err() {
+            sftp.mkdir(Path::new(&current), 0o755)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_files(sftp: &ssh2::Sftp, dir: &str, prefix: &str, objects: &mut Vec<String>) -> Result<()> {
+    for (name, stat) in read_dir(sftp, dir)? {
+        let object = format!("{prefix}{name}");
+
+        if stat.is_dir() {
+            collect_files(sftp, &format!("{dir}/{name}"), &format!("{object}/"), objects)?;
+        } else if !name.ends_with(".part") {
+            objects.push(object);
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_dir_all(sftp: &ssh2::Sftp, dir: &str) -> Result<()> {
+    for (name, stat) in read_dir(sftp, dir)? {
+        let path = format!("{dir}/{name}");
+
+        match stat.is_dir() {
+            true => remove_dir_all(sftp, &path)?,
+            false => sftp.unlink(Path::new(&path))?,
+        }
+    }
+
+    Ok(sftp.rmdir(Path::new(dir))?)
+}
+
+/// Remote file that is renamed to its final name on `finish`.
+struct SftpFile {
+    file: Option<ssh2::File>,
+    part_path: String,
+    full_path: String,
+    session: Arc<Mutex<Option<ssh2::Sftp>>>,
+}
+
+#[async_trait]
+impl ObjectWriter for SftpFile {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        let mut file = self.file.take().ok_or_else(|| Error::Storage("SFTP file is closed".to_string()))?;
+        let data = buf.to_vec();
+
+        let file = blocking(move || {
+            file.write_all(&data)?;
+            Ok(file)
+        }).await?;
+
+        self.file = Some(file);
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        let file = self.file.take();
+        let part_path = self.part_path.clone();
+        let full_path = self.full_path.clone();
+        let session = self.session.clone();
+
+        blocking(move || {
+            let mut file = file.ok_or_else(|| Error::Storage("SFTP file is closed".to_string()))?;
+
+            match file.fsync() {
+                Ok(()) => {}
+                // fsync@openssh.com is an extension, without it the close has to do
+                Err(err) if err.code() == ErrorCode::SFTP(SFTP_OP_UNSUPPORTED) => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            // A failed close can mean lost data, the part file must not become the object then
+            file.close()?;
+
+            let cached = session.lock().map_err(|_| Error::Storage("SFTP session is poisoned".to_string()))?;
+            let sftp = cached.as_ref().ok_or_else(|| Error::Storage("SFTP session is closed".to_string()))?;
+
+            let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+            let (part_path, full_path) = (Path::new(&part_path), Path::new(&full_path));
+
+            if sftp.rename(part_path, full_path, Some(flags)).is_err() {
+                // Servers without posix-rename refuse to overwrite
+                if sftp.stat(full_path).is_ok() {
+                    sftp.unlink(full_path)?;
+                }
+                sftp.rename(part_path, full_path, None)?;
+            }
+
+            Ok(())
+        }).await
+    }
+}
+
+struct SftpReader {
+    file: Option<ssh2::File>,
+}
+
+#[async_trait]
+impl ObjectReader for SftpReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let (file, chunk) = blocking(move || {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let read = file.read(&mut chunk)?;
+            chunk.truncate(read);
+            Ok((file, chunk))
+        }).await?;
+
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+
+        self.file = Some(file);
+        Ok(Some(chunk))
+    }
+}
+
@@ -0,0 +1,336 @@
+use async_trait::async_trait;
+use quick_xml::events::Event;
+use reqwest::{Body, Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::{Arc, Mutex}};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::http::{check_status, uri_decode, uri_encode};
+use super::{BackupSink, ObjectReader, ObjectWriter};
+use crate::{Error, Result};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/></d:prop></d:propfind>"#;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebDavConfig {
+    /// Collection that receives `<name>/<run>/<file>`,
+    /// e.g. `https://nas/remote.php/dav/files/user/backups` for Nextcloud.
+    pub url: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Bearer token, used instead of `user`/`password`.
+    pub token: Option<String>,
+}
+
+/// Stores runs as collections of a WebDAV server.
+#[derive(Clone)]
+pub struct WebDav {
+    config: WebDavConfig,
+    root: String,
+    client: reqwest::Client,
+    created: Arc<Mutex<HashSet<String>>>,
+}
+
+impl WebDav {
+    pub fn new(config: WebDavConfig, name: &str) -> Result<Self> {
+        if config.url.is_empty() {
+            return Err(Error::Config(format!("WebDAV url of \"{name}\" can not be empty")));
+        }
+
+        let root = format!("{}/{}", config.url.trim_end_matches('/'), uri_encode(name, true));
+        Ok(WebDav { config, root, client: reqwest::Client::new(), created: Arc::new(Mutex::new(HashSet::new())) })
+    }
+
+    fn url(&self, parts: &[&str]) -> String {
+        let mut url = self.root.clone();
+
+        for part in parts {
+            for segment in part.split('/').filter(|segment| !segment.is_empty()) {
+                url.push('/');
+                url.push_str(&uri_encode(segment, true));
+            }
+        }
+
+        url
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+
+        match (&self.config.token, &self.config.user) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some(user)) => request.basic_auth(user, self.config.password.as_ref()),
+            (None, None) => request,
+        }
+    }
+
+    /// MKCOL for the collection of `parts` and its parents below the configured url.
+    async fn create_collections(&self, parts: &[&str]) -> Result<()> {
+        let mut url = self.root.clone();
+        let mut urls = vec![url.clone()];
+
+        for part in parts {
+            for segment in part.split('/').filter(|segment| !segment.is_empty()) {
+                url.push('/');
+                url.push_str(&uri_encode(segment, true));
+                urls.push(url.clone());
+            }
+        }
+
+        for url in urls {
+            if self.created.lock().map(|created| created.contains(&url)).unwrap_or_default() {
+                continue;
+            }
+
+            let response = self.request(dav_method("MKCOL"), &url).send().await?;
+
+            // 405 means the collection already exists
+            if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                check_status(response).await?;
+            }
+
+            if let Ok(mut created) = self.created.lock() {
+                created.insert(url);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Direct children of a collection as (name, is collection).
+    async fn list(&self, url: &str) -> Result<Vec<(String, bool)>> {
+        let response = self.request(dav_method("PROPFIND"), &format!("{url}/"))
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml")
+            .body(PROPFIND_BODY)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+
+        let body = check_status(response).await?.text().await?;
+        let own_path = url_path(url).trim_end_matches('/').to_string();
+
+        Ok(parse_propfind(&body)?
+            .into_iter()
+            .filter_map(|(href, collection)| {
+                let path = url_path(&href).trim_end_matches('/').to_string();
+                if uri_decode(&path) == uri_decode(&own_path) {
+                    return None;
+                }
+                let name = uri_decode(path.rsplit('/').next().unwrap_or_default());
+                Some((name, collection))
+            })
+            .collect())
+    }
+
+    async fn collect_files(&self, run: &str, prefix: String, objects: &mut Vec<String>) -> Result<()> {
+        let mut pending = vec![prefix];
+
+        while let Some(prefix) = pending.pop() {
+            for (name, collection) in self.list(&self.url(&[run, &prefix])).await? {
+                let path = format!("{prefix}{name}");
+
+                if collection {
+                    pending.push(format!("{path}/"));
+                } else if !name.ends_with(".part") {
+                    objects.push(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BackupSink for WebDav {
+    fn describe(&self) -> String {
+        self.root.clone()
+    }
+
+    async fn create_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectWriter>> {
+        let parent = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or_default();
+        self.create_collections(&[run, parent]).await?;
+
+        let url = self.url(&[run, path]);
+        let part_url = format!("{url}.part");
+
+        // The body is streamed from the channel while the backup writes into it
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>(4);
+        let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (Ok::<_, std::io::Error>(chunk), receiver))
+        });
+
+        let request = self.request(Method::PUT, &part_url).body(Body::wrap_stream(stream));
+        let upload = tokio::spawn(async move { check_status(request.send().await?).await });
+
+        Ok(Box::new(WebDavObject { sink: self.clone(), sender: Some(sender), upload, part_url, url }))
+    }
+
+    async fn finalize(&self, _run: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_runs(&self) -> Result<Vec<String>> {
+        let mut runs: Vec<String> = self.list(&self.root).await?
+            .into_iter()
+            .filter(|(_, collection)| *collection)
+            .map(|(name, _)| name)
+            .collect();
+        runs.sort();
+        Ok(runs)
+    }
+
+    async fn list_objects(&self, run: &str) -> Result<Vec<String>> {
+        let mut objects = Vec::new();
+        self.collect_files(run, String::new(), &mut objects).await?;
+        objects.sort();
+        Ok(objects)
+    }
+
+    async fn open_object(&self, run: &str, path: &str) -> Result<Box<dyn ObjectReader>> {
+        let response = self.request(Method::GET, &self.url(&[run, path])).send().await?;
+        Ok(Box::new(WebDavReader { response: check_status(response).await? }))
+    }
+
+    async fn delete_object(&self, run: &str, path: &str) -> Result<()> {
+        let response = self.request(Method::DELETE, &self.url(&[run, path])).send().await?;
+
+        if response.status() != StatusCode::NOT_FOUND {
+            check_status(response).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_run(&self, run: &str) -> Result<()> {
+        let response = self.request(Method::DELETE, &format!("{}/", self.url(&[run]))).send().await?;
+
+        if response.status() != StatusCode::NOT_FOUND {
+            check_status(response).await?;
+        }
+
+        let run_url = self.url(&[run]);
+        if let Ok(mut created) = self.created.lock() {
+            created.retain(|url| *url != run_url && !url.starts_with(&format!("{run_url}/")));
+        }
+
+        Ok(())
+    }
+}
+
+/// Upload to `<file>.part` that is moved to its final name on `finish`.
+struct WebDavObject {
+    sink: WebDav,
+    sender: Option<mpsc::Sender<Vec<u8>>>,
+    upload: JoinHandle<Result<Response>>,
+    part_url: String,
+    url: String,
+}
+
+impl WebDavObject {
+    async fn upload_error(&mut self) -> Error {
+        self.sender = None;
+
+        match (&mut self.upload).await {
+            Ok(Err(err)) => err,
+            Ok(Ok(_)) => Error::Storage(format!("Upload of {} ended early", self.part_url)),
+            Err(err) => Error::Storage(format!("Upload of {} failed: {err}", self.part_url)),
+        }
+    }
+}
+
+#[async_trait]
+impl ObjectWriter for WebDavObject {
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return Err(Error::Storage(format!("Upload of {} is closed", self.part_url))),
+        };
+
+        if sender.send(buf.to_vec()).await.is_err() {
+            return Err(self.upload_error().await);
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self: Box<Self>) -> Result<()> {
+        self.sender = None;
+
+        match (&mut self.upload).await {
+            Ok(result) => result?,
+            Err(err) => return Err(Error::Storage(format!("Upload of {} failed: {err}", self.part_url))),
+        };
+
+        let response = self.sink.request(dav_method("MOVE"), &self.part_url)
+            .header("Destination", &self.url)
+            .header("Overwrite", "T")
+            .send()
+            .await?;
+        check_status(response).await?;
+
+        Ok(())
+    }
+}
+
+struct WebDavReader {
+    response: Response,
+}
+
+#[async_trait]
+impl ObjectReader for WebDavReader {
+    async fn read_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.response.chunk().await?.map(|chunk| chunk.to_vec()))
+    }
+}
+
+fn dav_method(name: &str) -> Method {
+    Method::from_bytes(name.as_bytes()).expect("WebDAV method names are valid tokens")
+}
+
+/// Path part of an absolute url or href.
+fn url_path(url: &str) -> &str {
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|index| &rest[index..]).unwrap_or("/"),
+        None => url,
+    }
+}
+
+/// (href, is collection) of every `response` of a multistatus body.
+fn parse_propfind(body: &str) -> Result<Vec<(String, bool)>> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    let mut entries = Vec::new();
+    let mut href = String::new();
+    let mut collection = false;
+    let mut in_href = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => match start.local_name().as_ref() {
+                b"response" => {
+                    href.clear();
+                    collection = false;
+                }
+                b"href" => in_href = true,
+                b"collection" => collection = true,
+                _ => {}
+            },
+            Ok(Event::Empty(empty)) if empty.local_name().as_ref() == b"collection" => collection = true,
+            Ok(Event::Text(text)) if in_href => {
+                href.push_str(&text.unescape().map_err(|err| Error::Storage(err.to_string()))?);
+            }
+            Ok(Event::End(end)) => match end.local_name().as_ref() {
+                b"href" => in_href = false,
+                b"response" => entries.push((href.clone(), collection)),
+                _ => {}
+            },
+            Ok(Event::Eof) => return Ok(entries),
+            Err(err) => return Err(Error::Storage(format!("Invalid PROPFIND response: {err}"))),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Checks of the values of a parsed config, before anything is backed up with it.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::backuper::{ConfigConnect, LifecycleConfig};
+use crate::config::Locations;
+
+/// Smallest `interval` of a connection, in hours (3 minutes).
+pub const MIN_INTERVAL: f64 = 0.05;
+
+/// Characters that can not be in the name of a connection, it names the directory of its runs.
+const UNSAFE_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Every problem of `config`, the connections of `text` in `path`, as `<path>:<line>:<column>: <message>`.
+pub fn validate(path: &Path, text: &str, config: &[ConfigConnect]) -> Vec<String> {
+    let locations = Locations::new(path, text);
+    let mut problems = Vec::new();
+    let mut names: HashMap<String, usize> = HashMap::new();
+
+    for (index, cfg_connect) in config.iter().enumerate() {
+        let mut problem = |key: &str, message: String| problems.push(format!("{}: {message}", locations.of(index, Some(key))));
+
+        if let Some(reason) = unsafe_name(&cfg_connect.name) {
+            problem("name", format!("name \"{}\" {reason}", cfg_connect.name));
+        }
+
+        // Names that only differ in case are the same directory on Windows and macOS
+        match names.get(&cfg_connect.name.to_lowercase()) {
+            Some(first) => problem("name", format!("name \"{}\" is already used by the connection at {}", cfg_connect.name, locations.of(*first, Some("name")))),
+            None => {
+                names.insert(cfg_connect.name.to_lowercase(), index);
+            }
+        }
+
+        // Variables and secret files are only read when connecting, they can be missing until then
+        let url_key = match cfg_connect.url_file.is_some() && cfg_connect.url.is_empty() {
+            true => "urlFile",
+            false => "url",
+        };
+
+        // A url that can not be built is reported by `to_job`
+        match cfg_connect.connection_url().map(|url| url.template()) {
+            Ok(Ok(Some(url))) => {
+                if let Err(err) = mongodb::options::ConnectionString::parse(&url) {
+                    problem(url_key, format!("url of \"{}\" is not a valid connection string: {}", cfg_connect.name, connection_string_error(&err)));
+                }
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(crate::Error::Config(message))) => problem(url_key, format!("url of \"{}\": {message}", cfg_connect.name)),
+            Ok(Err(err)) => problem(url_key, format!("url of \"{}\": {err}", cfg_connect.name)),
+            Err(_) => {}
+        }
+
+        if cfg_connect.interval.is_nan() || cfg_connect.interval < MIN_INTERVAL {
+            problem("interval", format!("interval of \"{}\" can not be lower than {MIN_INTERVAL} [3 min] ({})", cfg_connect.name, cfg_connect.interval));
+        }
+
+        if negative(cfg_connect.remove_old) {
+            problem("removeOld", format!("removeOld of \"{}\" can not be negative ({})", cfg_connect.name, cfg_connect.remove_old));
+        }
+
+        for (number, destination) in cfg_connect.destinations.iter().enumerate() {
+            let destination_name = destination.name.clone().unwrap_or_else(|| format!("#{}", number + 1));
+
+            if let Some(remove_old) = destination.remove_old.filter(|remove_old| negative(*remove_old)) {
+                problem("destinations", format!("removeOld of the destination \"{destination_name}\" of \"{}\" can not be negative ({remove_old})", cfg_connect.name));
+            }
+
+            let remove_old = destination.remove_old.unwrap_or(cfg_connect.remove_old);
+            if let Some(message) = destination.lifecycle.as_ref().and_then(|lifecycle| lifecycle_problem(lifecycle, remove_old)) {
+                problem("destinations", format!("{message} in the destination \"{destination_name}\" of \"{}\"", cfg_connect.name));
+            }
+        }
+
+        if let Some(message) = cfg_connect.lifecycle.as_ref().and_then(|lifecycle| lifecycle_problem(lifecycle, cfg_connect.remove_old)) {
+            problem("lifecycle", format!("{message} in \"{}\"", cfg_connect.name));
+        }
+    }
+
+    problems
+}
+
+/// Why `name` can not be the name of a directory on every system.
+fn unsafe_name(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        return Some("is empty");
+    }
+
+    if name == "." || name == ".." {
+        return Some("is not a directory name");
+    }
+
+    if name.contains(UNSAFE_CHARS) || name.contains(char::is_control) {
+        return Some("can not contain / \\ : * ? \" < > | or control characters");
+    }
+
+    if name != name.trim() || name.ends_with('.') {
+        return Some("can not start or end with a space or end with a dot");
+    }
+
+    None
+}
+
+/// `remove_old` is the retention the destination would have without the lifecycle.
+fn lifecycle_problem(lifecycle: &LifecycleConfig, remove_old: f64) -> Option<String> {
+    if negative(lifecycle.move_after) {
+        return Some(format!("moveAfter of the lifecycle can not be negative ({})", lifecycle.move_after));
+    }
+
+    // The tier replaces the retention, runs would be kept longer than removeOld says
+    if !negative(remove_old) && lifecycle.move_after > remove_old {
+        return Some(format!("moveAfter of the lifecycle can not be longer than removeOld ({} > {remove_old})", lifecycle.move_after));
+    }
+
+    match lifecycle.remove_old {
+        Some(remove_old) if negative(remove_old) => Some(format!("removeOld of the lifecycle can not be negative ({remove_old})")),
+        _ => None,
+    }
+}
+
+/// Days that can not be a retention or an age, NaN included.
+fn negative(days: f64) -> bool {
+    days.is_nan() || days < 0_f64
+}
+
+/// Message of a failed `ConnectionString::parse` without the kind and labels around it.
+fn connection_string_error(err: &mongodb::error::Error) -> String {
+    match err.kind.as_ref() {
+        mongodb::error::ErrorKind::InvalidArgument { message, .. } => message.clone(),
+        _ => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(text: &str) -> Vec<String> {
+        let path = Path::new("config.js");
+        let config: Vec<ConfigConnect> = crate::config::parse(path, text).unwrap();
+        validate(path, text, &config)
+    }
+
+    #[test]
+    fn valid_config() {
+        let text = "[\n  { name: 'a', url: 'mongodb://localhost:27017', interval: 1, removeOld: 30 },\n  { name: 'b.c', url: 'mongodb+srv://u:p@cluster.example.com', interval: 0.05, removeOld: 0 },\n]";
+        assert_eq!(problems(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn located_problems() {
+        let text = "[\n  { name: 'a', url: 'mongodb://localhost', interval: 0.01, removeOld: -1 },\n  { name: 'A', url: 'http://localhost', interval: 1, removeOld: 1 },\n]";
+
+        assert_eq!(problems(text), vec![
+            "config.js:2:44: interval of \"a\" can not be lower than 0.05 [3 min] (0.01)".to_string(),
+            "config.js:2:60: removeOld of \"a\" can not be negative (-1)".to_string(),
+            "config.js:3:5: name \"A\" is already used by the connection at config.js:2:5".to_string(),
+            "config.js:3:16: url of \"A\" is not a valid connection string: invalid connection string scheme: http".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn unsafe_names() {
+        assert_eq!(unsafe_name(""), Some("is empty"));
+        assert_eq!(unsafe_name(".."), Some("is not a directory name"));
+        assert!(unsafe_name("a/b").is_some());
+        assert!(unsafe_name("a\tb").is_some());
+        assert!(unsafe_name(" a").is_some());
+        assert!(unsafe_name("a.").is_some());
+        assert_eq!(unsafe_name("my db-1.prod"), None);
+    }
+
+    #[test]
+    fn secrets_are_not_read() {
+        let text = "[\n  { name: 'a', url: 'mongodb://u:${MONGO_BACKUPER_TEST_UNSET}@h', interval: 1, removeOld: 1 },\n  { name: 'b', urlFile: '/nonexistent/url', interval: 1, removeOld: 1 },\n  { name: 'c', url: 'mongodb://u@h', passwordFile: '/nonexistent/password', interval: 1, removeOld: 1 },\n]";
+        assert_eq!(problems(text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn template_problems() {
+        let text = "[\n  { name: 'a', url: 'mongodb://h', passwordFile: '/run/password', interval: 1, removeOld: 1 },\n  { name: 'b', url: 'mongodb://${A-B}@h', interval: 1, removeOld: 1 },\n]";
+
+        assert_eq!(problems(text), vec![
+            "config.js:2:16: url of \"a\": passwordFile needs a user name in the connection url".to_string(),
+            "config.js:3:16: url of \"b\": \"${A-B}\" is not an environment variable name".to_string(),
+        ]);
+    }
+    #[test]
+    fn lifecycle_longer_than_retention() {
+        let storage = "moveTo: { type: 'local', path: '/cold' }";
+        let text = format!("[\n  {{ name: 'a', url: 'mongodb://h', interval: 1, removeOld: 30, lifecycle: {{ moveAfter: 7, {storage} }} }},\n  {{ name: 'b', url: 'mongodb://h', interval: 1, removeOld: 30, lifecycle: {{ moveAfter: 60, {storage} }} }},\n  {{ name: 'c', url: 'mongodb://h', interval: 1, removeOld: 30, destinations: [{{ storage: {{ type: 'local' }}, removeOld: 5, lifecycle: {{ moveAfter: 7, {storage} }} }}] }},\n]");
+
+        assert_eq!(problems(&text), vec![
+            "config.js:3:64: moveAfter of the lifecycle can not be longer than removeOld (60 > 30) in \"b\"".to_string(),
+            "config.js:4:64: moveAfter of the lifecycle can not be longer than removeOld (7 > 5) in the destination \"#1\" of \"c\"".to_string(),
+        ]);
+    }
+}
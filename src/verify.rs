@@ -0,0 +1,154 @@
+use crate::archive::{ArchiveParser, Entry};
+use crate::crypto::{Key, Secret};
+use crate::job::{Compression, Format};
+use crate::reader::{DocumentSplitter, ObjectStream};
+use crate::report::{BackupReport, FileReport, MANIFEST_FILE};
+use crate::signing::{VerifyingKey, SIGNATURE_FILE};
+use crate::sink::BackupSink;
+use crate::Result;
+
+/// Result of checking a run against its manifest.
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    pub run: String,
+    pub files: usize,
+    /// Whether the files were decoded and their documents counted, not only checksummed.
+    /// Encrypted runs are only checksummed without a secret.
+    pub contents: bool,
+    /// Whether the signature of the manifest was checked.
+    pub signature: bool,
+    pub problems: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks size and sha256 of every file of `run` and, when they can be read,
+/// that they decode into the documents listed in the manifest. With `trusted` the
+/// manifest has to be signed by that key. Files missing from the manifest are reported too.
+pub async fn verify(sink: &dyn BackupSink, run: &str, secret: Option<&Secret>, trusted: Option<&VerifyingKey>) -> Result<VerifyReport> {
+    let data = crate::sink::read_object(sink, run, MANIFEST_FILE).await?;
+    let manifest: BackupReport = serde_json::from_slice(&data)?;
+
+    let (contents, key) = match (&manifest.encryption, secret) {
+        (Some(_), None) => (false, None),
+        _ => (true, manifest.key(secret)?),
+    };
+
+    let mut report = VerifyReport {
+        run: run.to_string(),
+        files: manifest.files.len(),
+        contents,
+        signature: trusted.is_some(),
+        problems: Vec::new(),
+    };
+
+    if let Some(trusted) = trusted {
+        let result = match crate::sink::read_object(sink, run, SIGNATURE_FILE).await {
+            Ok(signature) => crate::signing::check(trusted, &data, &signature),
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            report.problems.push(format!("{SIGNATURE_FILE}: {err}"));
+        }
+    }
+
+    for object in sink.list_objects(run).await? {
+        if object != MANIFEST_FILE && object != SIGNATURE_FILE && !manifest.files.iter().any(|file| file.path == object) {
+            report.problems.push(format!("{object}: not listed in the manifest"));
+        }
+    }
+
+    for file in &manifest.files {
+        if let Err(err) = verify_file(sink, &manifest, file, contents, key.as_ref(), &mut report.problems).await {
+            report.problems.push(format!("{}: {err}", file.path));
+        }
+    }
+
+    match report.is_ok() {
+        true => crate::logger::debug_string(format!("Run \"{run}\" of \"{}\" is intact", manifest.name)),
+        false => crate::logger::warn_string(format!("Run \"{run}\" of \"{}\" has {} problems", manifest.name, report.problems.len())),
+    }
+
+    Ok(report)
+}
+
+async fn verify_file(sink: &dyn BackupSink, manifest: &BackupReport, file: &FileReport, contents: bool, key: Option<&Key>, problems: &mut Vec<String>) -> Result<()> {
+    let compression = match contents {
+        true => manifest.compression,
+        false => Compression::None,
+    };
+
+    let mut stream = ObjectStream::open(sink, &manifest.run, &file.path, compression, key).await?;
+    let mut splitter = DocumentSplitter::new(file.format);
+    let mut parser = ArchiveParser::default();
+    let mut documents = 0;
+    let mut end = false;
+
+    // Both need the whole file
+    let whole = contents && matches!(file.format, Format::Parquet | Format::Sqlite);
+    let mut data = Vec::new();
+
+    while let Some(chunk) = stream.next_chunk().await? {
+        if !contents {
+            continue;
+        }
+
+        if whole {
+            data.extend_from_slice(&chunk);
+            continue;
+        }
+
+        match manifest.archive {
+            true => {
+                parser.push(&chunk);
+
+                while let Some(entry) = parser.next_entry()? {
+                    match entry {
+                        Entry::Document(_) => documents += 1,
+                        Entry::End => end = true,
+                        Entry::Namespace(..) => {}
+                    }
+                }
+            }
+            false => {
+                splitter.push(&chunk);
+
+                while splitter.next_document()?.is_some() {
+                    documents += 1;
+                }
+            }
+        }
+    }
+
+    let (bytes, sha256) = stream.checksum();
+    if bytes != file.bytes {
+        problems.push(format!("{}: {bytes} bytes, expected {}", file.path, file.bytes));
+    }
+    if sha256 != file.sha256 {
+        problems.push(format!("{}: checksum mismatch", file.path));
+    }
+
+    match file.format {
+        Format::Parquet if whole => documents = crate::export::read_rows(data, 0)?.0,
+        Format::Sqlite if whole => documents = crate::export::count_rows(&data)?,
+        _ => {}
+    }
+
+    if contents {
+        splitter.finish()?;
+
+        if manifest.archive && !end {
+            problems.push(format!("{}: archive has no end marker", file.path));
+        }
+        if documents != file.documents {
+            problems.push(format!("{}: {documents} documents, expected {}", file.path, file.documents));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use mongo_backuper::sink::{Azure, AzureConfig};
+
+/// Runs against the Azurite emulator or a storage account, see "Testing the storages" in the README.
+#[tokio::test]
+async fn azure() {
+    let container = match common::required("AZURE_CONTAINER") {
+        Some(res) => res,
+        None => return,
+    };
+
+    let config = AzureConfig {
+        account: common::env("AZURE_ACCOUNT").expect("MONGO_BACKUPER_TEST_AZURE_ACCOUNT"),
+        container,
+        prefix: common::env("AZURE_PREFIX").unwrap_or_default(),
+        endpoint: common::env("AZURE_ENDPOINT"),
+        account_key: common::env("AZURE_ACCOUNT_KEY"),
+        sas_token: common::env("AZURE_SAS_TOKEN"),
+    };
+
+    let name = common::unique_name();
+    common::exercise(&Azure::new(config, &name).unwrap(), &name).await;
+}
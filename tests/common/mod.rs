@@ -0,0 +1,94 @@
+//! Checks that every storage behaves the same. The tests of the remote storages only run when
+//! their `MONGO_BACKUPER_TEST_*` environment variables point at a server, see the README.
+
+// Every test file uses only a part of it
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use mongo_backuper::sink::{read_object, write_object};
+use mongo_backuper::BackupSink;
+
+const OLD_RUN: &str = "2020.01.01 00-00";
+
+/// Value of `MONGO_BACKUPER_TEST_<name>`, `None` when it is not set.
+pub fn env(name: &str) -> Option<String> {
+    std::env::var(format!("MONGO_BACKUPER_TEST_{name}")).ok().filter(|value| !value.is_empty())
+}
+
+/// `MONGO_BACKUPER_TEST_<name>`, `None` with a note that the test is skipped when it is not set.
+pub fn required(name: &str) -> Option<String> {
+    let value = env(name);
+    if value.is_none() {
+        eprintln!("Skipped, MONGO_BACKUPER_TEST_{name} is not set");
+    }
+    value
+}
+
+/// Connection name that no earlier test used, the runs of a test are stored below it.
+pub fn unique_name() -> String {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("mongo_backuper-test-{nanos}")
+}
+
+/// Bytes that do not repeat.
+fn sample(len: usize) -> Vec<u8> {
+    let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect()
+}
+
+async fn runs(sink: &dyn BackupSink) -> Vec<String> {
+    let mut runs = sink.list_runs().await.unwrap();
+    runs.sort();
+    runs
+}
+
+async fn objects(sink: &dyn BackupSink, run: &str) -> Vec<String> {
+    let mut objects = sink.list_objects(run).await.unwrap();
+    objects.sort();
+    objects
+}
+
+/// Writes, lists, reads and deletes runs and objects of the empty `sink`, then removes an old
+/// run with retention. `sink` is empty again afterwards.
+pub async fn exercise(sink: &dyn BackupSink, name: &str) {
+    let new_run = mongo_backuper::exts::get_date_file();
+    // Larger than the smallest S3 part, so that it is uploaded in parts
+    let data = sample(6 * 1024 * 1024 + 123);
+
+    assert!(runs(sink).await.is_empty(), "{} is not empty", sink.describe());
+
+    write_object(sink, OLD_RUN, "db/collection.bson.gz", &data).await.unwrap();
+    write_object(sink, OLD_RUN, "manifest.json", b"{}").await.unwrap();
+    sink.finalize(OLD_RUN).await.unwrap();
+
+    write_object(sink, &new_run, "db/other collection.bson", b"small").await.unwrap();
+    write_object(sink, &new_run, "manifest.json", b"{}").await.unwrap();
+    sink.finalize(&new_run).await.unwrap();
+
+    assert_eq!(runs(sink).await, vec![OLD_RUN.to_string(), new_run.clone()]);
+    assert_eq!(objects(sink, OLD_RUN).await, vec!["db/collection.bson.gz", "manifest.json"]);
+    assert_eq!(read_object(sink, OLD_RUN, "db/collection.bson.gz").await.unwrap(), data);
+    assert_eq!(read_object(sink, &new_run, "db/other collection.bson").await.unwrap(), b"small");
+
+    // Objects are replaced, a missing object is not an error to delete but to read
+    write_object(sink, &new_run, "db/other collection.bson", b"replaced").await.unwrap();
+    assert_eq!(read_object(sink, &new_run, "db/other collection.bson").await.unwrap(), b"replaced");
+
+    sink.delete_object(&new_run, "db/other collection.bson").await.unwrap();
+    sink.delete_object(&new_run, "db/other collection.bson").await.unwrap();
+    assert_eq!(objects(sink, &new_run).await, vec!["manifest.json"]);
+    assert!(read_object(sink, &new_run, "db/other collection.bson").await.is_err());
+
+    // Retention removes the old run and keeps the newest
+    assert!(mongo_backuper::retention::delete_old_runs(sink, name, Duration::from_secs(24 * 3600)).await);
+    assert_eq!(runs(sink).await, vec![new_run.clone()]);
+
+    sink.delete_run(&new_run).await.unwrap();
+    assert!(runs(sink).await.is_empty());
+}
@@ -0,0 +1,18 @@
+mod common;
+
+use mongo_backuper::sink::Memory;
+use mongo_backuper::LocalFs;
+
+#[tokio::test]
+async fn memory() {
+    common::exercise(&Memory::new(), &common::unique_name()).await;
+}
+
+#[tokio::test]
+async fn local() {
+    let name = common::unique_name();
+    let root = std::env::temp_dir().join(&name);
+
+    common::exercise(&LocalFs::new(root.clone()), &name).await;
+    std::fs::remove_dir_all(root).unwrap_or_default();
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use mongo_backuper::sink::{S3Config, S3};
+
+/// Runs against MinIO or any other S3 API, see "Testing the storages" in the README.
+#[tokio::test]
+async fn s3() {
+    let endpoint = match common::required("S3_ENDPOINT") {
+        Some(res) => res,
+        None => return,
+    };
+
+    let config = S3Config {
+        endpoint,
+        region: common::env("S3_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+        bucket: common::env("S3_BUCKET").expect("MONGO_BACKUPER_TEST_S3_BUCKET"),
+        prefix: common::env("S3_PREFIX").unwrap_or_default(),
+        access_key: common::env("S3_ACCESS_KEY").expect("MONGO_BACKUPER_TEST_S3_ACCESS_KEY"),
+        secret_key: common::env("S3_SECRET_KEY").expect("MONGO_BACKUPER_TEST_S3_SECRET_KEY"),
+        // The smallest part, the test object is uploaded in two parts
+        part_size: 5,
+        path_style: common::env("S3_VIRTUAL_HOSTS").is_none(),
+        storage_class: None,
+    };
+
+    let name = common::unique_name();
+    common::exercise(&S3::new(config, &name).unwrap(), &name).await;
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use mongo_backuper::sink::{Sftp, SftpConfig};
+
+/// Runs against an OpenSSH server, see "Testing the storages" in the README.
+#[tokio::test]
+async fn sftp() {
+    let host = match common::required("SFTP_HOST") {
+        Some(res) => res,
+        None => return,
+    };
+
+    let config = SftpConfig {
+        host,
+        port: common::env("SFTP_PORT").map(|port| port.parse().expect("MONGO_BACKUPER_TEST_SFTP_PORT")).unwrap_or(22),
+        user: common::env("SFTP_USER").expect("MONGO_BACKUPER_TEST_SFTP_USER"),
+        key_file: common::env("SFTP_KEY_FILE"),
+        passphrase: common::env("SFTP_PASSPHRASE"),
+        password: common::env("SFTP_PASSWORD"),
+        path: common::env("SFTP_PATH").expect("MONGO_BACKUPER_TEST_SFTP_PATH"),
+        fingerprint: common::env("SFTP_FINGERPRINT"),
+        known_hosts: common::env("SFTP_KNOWN_HOSTS"),
+    };
+
+    let name = common::unique_name();
+    common::exercise(&Sftp::new(config, &name).unwrap(), &name).await;
+}
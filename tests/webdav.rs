@@ -0,0 +1,22 @@
+mod common;
+
+use mongo_backuper::sink::{WebDav, WebDavConfig};
+
+/// Runs against a WebDAV server like `rclone serve webdav`, see "Testing the storages" in the README.
+#[tokio::test]
+async fn webdav() {
+    let url = match common::required("WEBDAV_URL") {
+        Some(res) => res,
+        None => return,
+    };
+
+    let config = WebDavConfig {
+        url,
+        user: common::env("WEBDAV_USER"),
+        password: common::env("WEBDAV_PASSWORD"),
+        token: common::env("WEBDAV_TOKEN"),
+    };
+
+    let name = common::unique_name();
+    common::exercise(&WebDav::new(config, &name).unwrap(), &name).await;
+}